@@ -1,34 +1,129 @@
+mod annotation;
+mod builder;
+mod schema;
+
+pub(crate) use self::annotation::doc_comment;
+use self::annotation::Annotation;
+pub use self::builder::ConfigBuilder;
+pub(crate) use self::schema::comments_to_description;
+use self::schema::description_to_comments;
+
 use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 use toml_edit::{Decor, DocumentMut, Item, Table, Value};
 
-use crate::output::{Output, OutputFormat};
-use crate::{ConfigErr, ConfigResult, ConfigType, ConfigValue};
+use crate::expr::{Expr, ExprValue};
+use crate::output::{DumpOptions, Output, OutputFormat};
+use crate::ty::resolve_aliases;
+use crate::value::check_array_lens;
+use crate::{
+    ConfigErr, ConfigResult, ConfigType, ConfigValue, Constraint, UpdateReport, UpdateWarning,
+};
 
 type ConfigTable = BTreeMap<String, ConfigItem>;
 
+/// The origin of a config value.
+///
+/// It contains the name of the layer (e.g., `spec`, `oldconfig`, `overlay`)
+/// and the path of the file (or the name of the environment variable, or the
+/// chosen variant) that the value came from, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigSource {
+    layer: String,
+    path: Option<String>,
+}
+
+impl ConfigSource {
+    /// Creates a new config source with the layer name and the file path.
+    pub fn new(layer: &str, path: Option<&str>) -> Self {
+        Self {
+            layer: layer.into(),
+            path: path.map(Into::into),
+        }
+    }
+
+    /// Returns the name of the layer.
+    pub fn layer(&self) -> &str {
+        &self.layer
+    }
+
+    /// Returns the path of the file or the name of the environment variable,
+    /// if any.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{} ({})", self.layer, path)
+        } else {
+            write!(f, "{}", self.layer)
+        }
+    }
+}
+
 /// A structure representing a config item.
 ///
-/// It contains the config key, value and comments.
+/// It contains the config key, value, comments and the provenance of the
+/// value.
 #[derive(Debug, Clone)]
 pub struct ConfigItem {
     table_name: String,
     key: String,
     value: ConfigValue,
     comments: String,
+    doc: String,
+    deprecated: Option<String>,
+    renamed_from: Vec<String>,
+    selector: Option<String>,
+    variants: Vec<(String, ConfigValue)>,
+    variant: Option<String>,
+    history: Vec<(ConfigSource, ConfigValue)>,
 }
 
 impl ConfigItem {
-    fn new(table_name: &str, table: &Table, key: &str, value: &Value) -> ConfigResult<Self> {
+    fn new(
+        table_name: &str,
+        table: &Table,
+        key: &str,
+        value: &Value,
+        aliases: &BTreeMap<String, ConfigType>,
+    ) -> ConfigResult<Self> {
         let inner = || {
             let item = table.key(key).unwrap();
             let comments = prefix_comments(item.leaf_decor())
                 .unwrap_or_default()
                 .to_string();
             let suffix = suffix_comments(value.decor()).unwrap_or_default().trim();
-            let value = if !suffix.is_empty() {
-                let ty_str = suffix.trim_start_matches('#');
-                let ty = ConfigType::new(ty_str)?;
-                ConfigValue::from_raw_value_type(value, ty)?
+            let (annotation, doc) = Annotation::parse_comment(suffix, aliases)?;
+            let mut deprecated = None;
+            let mut renamed_from = Vec::new();
+            let mut selector = None;
+            let mut variants = Vec::new();
+            let value = if let Some(annotation) = annotation {
+                deprecated = annotation.deprecated;
+                renamed_from = annotation.renamed_from;
+                if let Some(by) = annotation.by {
+                    let Value::InlineTable(table) = value else {
+                        return Err(ConfigErr::Other(
+                            "`by(..)` requires a table of value variants".into(),
+                        ));
+                    };
+                    let (ty, constraints) = (&annotation.ty, &annotation.constraints);
+                    variants = parse_variants(table.iter(), ty, constraints)?;
+                    selector = Some(by);
+                    variants[0].1.clone()
+                } else {
+                    let mut value = ConfigValue::from_raw_value_type(value, annotation.ty)?;
+                    value.set_constraints(annotation.constraints)?;
+                    value
+                }
+            } else if value.is_inline_table() {
+                return Err(ConfigErr::Other(
+                    "Value variants require a `by(\"item\")` annotation".into(),
+                ));
             } else {
                 ConfigValue::from_raw_value(value)?
             };
@@ -37,6 +132,13 @@ impl ConfigItem {
                 key: key.into(),
                 value,
                 comments,
+                doc,
+                deprecated,
+                renamed_from,
+                selector,
+                variants,
+                variant: None,
+                history: Vec::new(),
             })
         };
         let res = inner();
@@ -46,8 +148,13 @@ impl ConfigItem {
         res
     }
 
-    fn new_global(table: &Table, key: &str, value: &Value) -> ConfigResult<Self> {
-        Self::new(Config::GLOBAL_TABLE_NAME, table, key, value)
+    fn new_global(
+        table: &Table,
+        key: &str,
+        value: &Value,
+        aliases: &BTreeMap<String, ConfigType>,
+    ) -> ConfigResult<Self> {
+        Self::new(Config::GLOBAL_TABLE_NAME, table, key, value, aliases)
     }
 
     /// Returns the unique name of the config item.
@@ -82,46 +189,252 @@ impl ConfigItem {
         &self.comments
     }
 
+    /// Returns the inline documentation of the config item, i.e., the prose
+    /// following the annotation on the same line (e.g., `# uint; number of
+    /// CPUs`). It is empty if not specified.
+    pub fn doc(&self) -> &str {
+        &self.doc
+    }
+
+    /// Returns the deprecation message if the config item is deprecated.
+    ///
+    /// The message may be empty.
+    pub fn deprecated(&self) -> Option<&str> {
+        self.deprecated.as_deref()
+    }
+
+    /// Returns the old names of the config item, whose values are migrated to
+    /// this item on [`Config::update`].
+    ///
+    /// Each name is a key in the same table, or an item name in the format
+    /// `table.key` if the item was in another table.
+    pub fn renamed_from(&self) -> &[String] {
+        &self.renamed_from
+    }
+
+    /// Returns the name of the item whose value selects among the value
+    /// variants (the `by("item")` annotation), if the item has variants.
+    pub fn selector(&self) -> Option<&str> {
+        self.selector.as_deref()
+    }
+
+    /// Returns the value variants of the item with their names, the `default`
+    /// one comes first. It is empty if the item has no variants.
+    pub fn variants(&self) -> &[(String, ConfigValue)] {
+        &self.variants
+    }
+
+    /// Returns the name of the chosen variant, if the variants have been
+    /// resolved (see [`Config::resolve_variants`]).
+    pub fn variant(&self) -> Option<&str> {
+        self.variant.as_deref()
+    }
+
     /// Returns the mutable reference to the value of the config item.
     pub fn value_mut(&mut self) -> &mut ConfigValue {
         &mut self.value
     }
+
+    /// Returns the name of the environment variable that overrides the value
+    /// of the config item (see [`Config::apply_env`]).
+    ///
+    /// The name is `prefix` followed by the table name, two underscores and
+    /// the key, all in uppercase and with `-` replaced by `_`. The table name
+    /// and the underscores are omitted for items in the global table. E.g.,
+    /// the item `kernel.task-stack-size` with the prefix `AX_CONFIG_` is
+    /// overridden by `AX_CONFIG_KERNEL__TASK_STACK_SIZE`.
+    pub fn env_name(&self, prefix: &str) -> String {
+        let name = if self.table_name == Config::GLOBAL_TABLE_NAME {
+            self.key.clone()
+        } else {
+            format!("{}__{}", self.table_name, self.key)
+        };
+        format!("{}{}", prefix, name.to_uppercase().replace('-', "_"))
+    }
+
+    /// Returns the source that the current value came from, if known.
+    pub fn source(&self) -> Option<&ConfigSource> {
+        self.history.last().map(|(source, _)| source)
+    }
+
+    /// Returns the provenance chain of the value.
+    ///
+    /// Each element is a source and the value it set, ordered from the
+    /// earliest layer to the latest one. The last element is the current
+    /// value.
+    pub fn history(&self) -> &[(ConfigSource, ConfigValue)] {
+        &self.history
+    }
+
+    /// Updates the value of the config item and records where the new value
+    /// came from.
+    ///
+    /// The new value overrides the value variants of the item, if any.
+    pub fn update_from(
+        &mut self,
+        new_value: ConfigValue,
+        source: ConfigSource,
+    ) -> ConfigResult<()> {
+        self.value.update(new_value)?;
+        self.history.push((source, self.value.clone()));
+        self.clear_variants();
+        Ok(())
+    }
+
+    fn clear_variants(&mut self) {
+        self.selector = None;
+        self.variants.clear();
+        self.variant = None;
+    }
+
+    /// Returns whether the item was named `name` (`key` or `table.key`).
+    fn renamed_from_name(&self, name: &str) -> bool {
+        self.renamed_from.iter().any(|old| {
+            if old.contains('.') || self.table_name == Config::GLOBAL_TABLE_NAME {
+                old == name
+            } else {
+                name.split_once('.') == Some((&self.table_name, old))
+            }
+        })
+    }
+
+    fn update_with(&mut self, other: &Self) -> ConfigResult<()> {
+        self.value.update(other.value.clone())?;
+        if let Some(source) = other.source() {
+            self.history.push((source.clone(), self.value.clone()));
+        }
+        // unresolved variants replace those of `self`, while an explicit value
+        // overrides them
+        if other.selector.is_some() && other.variant.is_none() {
+            self.selector = other.selector.clone();
+            self.variants = other.variants.clone();
+            self.variant = None;
+        } else {
+            self.clear_variants();
+        }
+        Ok(())
+    }
+
+    /// Updates the value with the other item, the value is coerced to the
+    /// type of `self` if the types mismatch, or ignored if it cannot be.
+    fn update_coerced(&mut self, other: &Self, report: &mut UpdateReport) -> ConfigResult<()> {
+        match self.update_with(other) {
+            Err(ConfigErr::ValueTypeMismatch) => {}
+            res => return res,
+        }
+        let type_name = |v: &ConfigValue| match v.ty() {
+            Some(ty) => ty.to_string(),
+            None => v.inferred_type().map_or("?".into(), |ty| ty.to_string()),
+        };
+        let value = other.value.to_toml_value();
+        for candidate in other.value.coercions(self.value.ty()) {
+            let mut coerced = other.clone();
+            coerced.value = candidate;
+            match self.update_with(&coerced) {
+                Ok(()) => {
+                    report.push(UpdateWarning::Coerced {
+                        item: self.item_name(),
+                        value,
+                        from: type_name(&other.value),
+                        to: type_name(&self.value),
+                    });
+                    return Ok(());
+                }
+                Err(ConfigErr::ValueTypeMismatch) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        report.push(UpdateWarning::Mismatched {
+            item: self.item_name(),
+            value,
+            ty: type_name(&self.value),
+        });
+        Ok(())
+    }
 }
 
 /// A structure storing all config items.
 ///
 /// It contains a global table and multiple named tables, each table is a map
 /// from key to value, the key is a string and the value is a [`ConfigItem`].
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Config {
     global: ConfigTable,
     tables: BTreeMap<String, ConfigTable>,
     table_comments: BTreeMap<String, String>,
+    rules: BTreeMap<String, Expr>,
+    depends_on: BTreeMap<String, Expr>,
+    types: BTreeMap<String, ConfigType>,
+    type_comments: BTreeMap<String, String>,
 }
 
 impl Config {
     /// The name of the global table of the config.
     pub const GLOBAL_TABLE_NAME: &'static str = "$GLOBAL";
 
+    /// The name of the special table that contains the validation rules.
+    ///
+    /// Each key in the table is the rule name, and the value is an expression
+    /// over config items that must evaluate to `true` (see
+    /// [`Config::check_rules`]).
+    pub const RULES_TABLE_NAME: &'static str = "__rules__";
+
+    /// The name of the special table that contains the conditions of items.
+    ///
+    /// Each key in the table is a table name or an item name (`key` or
+    /// `table.key`), and the value is an expression over config items. The
+    /// table or item is only enabled if the expression evaluates to `true`
+    /// (see [`Config::disabled_by`]).
+    pub const DEPENDS_ON_TABLE_NAME: &'static str = "__depends_on__";
+
+    /// The name of the special table that contains the type aliases.
+    ///
+    /// Each key in the table is the alias name, and the value is the type it
+    /// refers to (e.g., `region = "(uint, uint)"`), which can be used in the
+    /// type annotations (e.g., `# [region]`). See [`Config::add_type_alias`].
+    pub const TYPES_TABLE_NAME: &'static str = "__types__";
+
+    /// The top-level key of the paths of the files to include (see
+    /// [`Config::from_file`]).
+    pub const INCLUDE_KEY: &'static str = "include";
+
     /// Create a new empty config object.
     pub fn new() -> Self {
         Self {
             global: ConfigTable::new(),
             tables: BTreeMap::new(),
             table_comments: BTreeMap::new(),
+            rules: BTreeMap::new(),
+            depends_on: BTreeMap::new(),
+            types: BTreeMap::new(),
+            type_comments: BTreeMap::new(),
         }
     }
 
+    /// Returns a builder to construct a config object programmatically.
+    ///
+    /// See [`ConfigBuilder`] for details.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
     /// Returns whether the config object contains no items.
     pub fn is_empty(&self) -> bool {
         self.global.is_empty() && self.tables.is_empty()
     }
 
     fn new_table(&mut self, name: &str, comments: &str) -> ConfigResult<&mut ConfigTable> {
-        if name == Self::GLOBAL_TABLE_NAME {
+        if [
+            Self::GLOBAL_TABLE_NAME,
+            Self::RULES_TABLE_NAME,
+            Self::DEPENDS_ON_TABLE_NAME,
+            Self::TYPES_TABLE_NAME,
+        ]
+        .contains(&name)
+        {
             return Err(ConfigErr::Other(format!(
                 "Table name `{}` is reserved",
-                Self::GLOBAL_TABLE_NAME
+                name
             )));
         }
         if self.tables.contains_key(name) {
@@ -160,6 +473,35 @@ impl Config {
         self.table_at(table).and_then(|t| t.get(key))
     }
 
+    /// Returns the value of the config item with the specified name (`key` or
+    /// `table.key`), converted into a native Rust value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use axconfig_gen::Config;
+    /// let config = Config::from_toml(r#"
+    /// [platform]
+    /// phys-memory-size = 0x800_0000
+    /// mmio-regions = [["0xfec0_0000", "0x1000"]]
+    /// "#).unwrap();
+    /// assert_eq!(config.get::<u64>("platform.phys-memory-size").unwrap(), 0x800_0000);
+    /// assert_eq!(
+    ///     config.get::<Vec<(usize, usize)>>("platform.mmio-regions").unwrap(),
+    ///     [(0xfec0_0000, 0x1000)],
+    /// );
+    /// assert!(config.get::<bool>("platform.phys-memory-size").is_err());
+    /// ```
+    pub fn get<T>(&self, name: &str) -> ConfigResult<T>
+    where
+        T: for<'a> TryFrom<&'a ConfigValue, Error = ConfigErr>,
+    {
+        let item = self
+            .item_by_name(name)
+            .ok_or_else(|| ConfigErr::Other(format!("Config item `{}` not found", name)))?;
+        T::try_from(item.value())
+    }
+
     /// Returns the mutable reference to the config item with the specified
     /// table name and key.
     pub fn config_at_mut(&mut self, table: &str, key: &str) -> Option<&mut ConfigItem> {
@@ -194,30 +536,290 @@ impl Config {
     pub fn iter(&self) -> impl Iterator<Item = &ConfigItem> {
         self.table_iter().flat_map(|(_, c, _)| c.values())
     }
+
+    /// Returns the config items without explicit type annotations, along with
+    /// the types inferred from their values.
+    ///
+    /// The inferred type is [`ConfigType::Unknown`] if it cannot be inferred
+    /// (e.g., for an empty array), such items are skipped on dump unless in
+    /// strict mode (see [`DumpOptions::strict`]).
+    pub fn inferred_items(&self) -> ConfigResult<Vec<(&ConfigItem, ConfigType)>> {
+        self.iter()
+            .filter(|item| item.value.ty().is_none())
+            .map(|item| Ok((item, item.value.inferred_type()?)))
+            .collect()
+    }
+
+    /// Returns an error if any config item has no explicit type annotation,
+    /// as required in strict mode (see [`DumpOptions::strict`]).
+    ///
+    /// It is also checked on dump in strict mode, but checking the
+    /// specifications early rejects them before any other config is applied.
+    pub fn check_strict(&self) -> ConfigResult<()> {
+        if let Some((item, ty)) = self.inferred_items()?.first() {
+            return Err(ConfigErr::Other(format!(
+                "Config item `{}` has no type annotation (inferred as `{}`)",
+                item.item_name(),
+                ty
+            )));
+        }
+        Ok(())
+    }
+
+    /// Adds a new empty table with the documentation `comments`.
+    ///
+    /// `comments` is the plain text without the leading `#`. Returns an
+    /// error if the table name is reserved (e.g., [`Config::GLOBAL_TABLE_NAME`])
+    /// or the table already exists.
+    pub fn add_table(&mut self, name: &str, comments: &str) -> ConfigResult<()> {
+        self.new_table(name, &description_to_comments(comments))?;
+        Ok(())
+    }
+
+    /// Removes the table with the specified name and returns its items.
+    ///
+    /// The global table cannot be removed. Returns an error if the table does
+    /// not exist, or a rule or condition refers to the table or its items.
+    pub fn remove_table(&mut self, name: &str) -> ConfigResult<BTreeMap<String, ConfigItem>> {
+        if !self.tables.contains_key(name) {
+            return Err(ConfigErr::Other(format!("Table `{}` not found", name)));
+        }
+        let prefix = format!("{}.", name);
+        self.check_unreferenced(&format!("Table `{}`", name), |n| {
+            n == name || n.starts_with(&prefix)
+        })?;
+        self.table_comments.remove(name);
+        Ok(self.tables.remove(name).unwrap())
+    }
+
+    /// Inserts a new config item into the table with the specified name.
+    ///
+    /// Use [`Config::GLOBAL_TABLE_NAME`] for the global table. If `ty` is
+    /// specified, the value must match it (as well as the type of `value` if
+    /// any). `comments` is the plain text without the leading `#`.
+    ///
+    /// Returns an error if the table does not exist or the key already exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use axconfig_gen::{Config, ConfigType, ConfigValue};
+    /// let mut config = Config::new();
+    /// config.add_table("platform", "Platform configs").unwrap();
+    /// let value = ConfigValue::new("0x800_0000").unwrap();
+    /// config
+    ///     .insert("platform", "phys-memory-size", value, Some(ConfigType::Uint), "Size of memory.")
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     config.dump_toml().unwrap(),
+    ///     "# Platform configs\n[platform]\n# Size of memory.\nphys-memory-size = 0x800_0000 # uint\n",
+    /// );
+    /// ```
+    pub fn insert(
+        &mut self,
+        table: &str,
+        key: &str,
+        mut value: ConfigValue,
+        ty: Option<ConfigType>,
+        comments: &str,
+    ) -> ConfigResult<()> {
+        let configs = self
+            .table_at_mut(table)
+            .ok_or_else(|| ConfigErr::Other(format!("Table `{}` not found", table)))?;
+        if configs.contains_key(key) {
+            return Err(ConfigErr::Other(format!("Duplicate key `{}`", key)));
+        }
+        if let Some(ty) = ty {
+            value.set_type(ty)?;
+        }
+        let item = ConfigItem {
+            table_name: table.into(),
+            key: key.into(),
+            value,
+            comments: description_to_comments(comments),
+            doc: String::new(),
+            deprecated: None,
+            renamed_from: Vec::new(),
+            selector: None,
+            variants: Vec::new(),
+            variant: None,
+            history: Vec::new(),
+        };
+        configs.insert(key.into(), item);
+        Ok(())
+    }
+
+    /// Removes the config item with the specified table name and key, and
+    /// returns it.
+    ///
+    /// Returns an error if the item does not exist, or a rule or condition
+    /// refers to it.
+    pub fn remove(&mut self, table: &str, key: &str) -> ConfigResult<ConfigItem> {
+        let name = self
+            .config_at(table, key)
+            .ok_or_else(|| {
+                ConfigErr::Other(format!("Config item `{}` not found in `{}`", key, table))
+            })?
+            .item_name();
+        self.check_unreferenced(&format!("Config item `{}`", name), |n| n == name)?;
+        Ok(self.table_at_mut(table).unwrap().remove(key).unwrap())
+    }
+
+    /// Renames the key of the config item in the specified table.
+    ///
+    /// Returns an error if the item does not exist, the new key already
+    /// exists, or a rule or condition refers to the item.
+    pub fn rename(&mut self, table: &str, key: &str, new_key: &str) -> ConfigResult<()> {
+        if let Some(item) = self.config_at(table, key) {
+            let name = item.item_name();
+            self.check_unreferenced(&format!("Config item `{}`", name), |n| n == name)?;
+        }
+        let configs = self
+            .table_at_mut(table)
+            .ok_or_else(|| ConfigErr::Other(format!("Table `{}` not found", table)))?;
+        if configs.contains_key(new_key) {
+            return Err(ConfigErr::Other(format!("Duplicate key `{}`", new_key)));
+        }
+        let mut item = configs.remove(key).ok_or_else(|| {
+            ConfigErr::Other(format!("Config item `{}` not found in `{}`", key, table))
+        })?;
+        item.key = new_key.into();
+        configs.insert(new_key.into(), item);
+        Ok(())
+    }
+
+    /// Returns an error if a rule or condition refers to a name matching
+    /// `refers`, since it would be left dangling.
+    fn check_unreferenced(&self, what: &str, refers: impl Fn(&str) -> bool) -> ConfigResult<()> {
+        if let Some((rule, _)) = self
+            .rules
+            .iter()
+            .find(|(_, expr)| expr.idents().into_iter().any(&refers))
+        {
+            return Err(ConfigErr::Other(format!(
+                "{} is referred to by rule `{}`",
+                what, rule
+            )));
+        }
+        if let Some((name, _)) = self
+            .depends_on
+            .iter()
+            .find(|(name, expr)| refers(name) || expr.idents().into_iter().any(&refers))
+        {
+            return Err(ConfigErr::Other(format!(
+                "{} is referred to by the condition of `{}`",
+                what, name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Records `source` as the origin of the values of all config items.
+    ///
+    /// The previous provenance of the items is discarded.
+    pub fn set_source(&mut self, source: ConfigSource) {
+        for item in self.items_mut() {
+            item.history = vec![(source.clone(), item.value.clone())];
+        }
+    }
+
+    /// Sets the layer name (e.g., `spec`) in the provenance of all config
+    /// items, keeping the paths of the files the values came from (see
+    /// [`Config::from_file`]).
+    pub fn set_layer(&mut self, layer: &str) {
+        for item in self.items_mut() {
+            for (source, _) in item.history.iter_mut() {
+                source.layer = layer.into();
+            }
+        }
+    }
+
+    fn items_mut(&mut self) -> impl Iterator<Item = &mut ConfigItem> {
+        [&mut self.global]
+            .into_iter()
+            .chain(self.tables.values_mut())
+            .flat_map(|t| t.values_mut())
+    }
 }
 
 impl Config {
     /// Parse a toml string into a config object.
+    ///
+    /// The `include` directive is not supported, use [`Config::from_file`] to
+    /// load the files containing it.
     pub fn from_toml(toml: &str) -> ConfigResult<Self> {
         let doc = toml.parse::<DocumentMut>()?;
-        let table = doc.as_table();
-
+        if doc.contains_key(Self::INCLUDE_KEY) {
+            return Err(ConfigErr::Other(format!(
+                "`{}` is only supported in files loaded by `Config::from_file`",
+                Self::INCLUDE_KEY
+            )));
+        }
         let mut result = Self::new();
+        result.parse_document(doc.as_table())?;
+        Ok(result)
+    }
+
+    /// Reads a TOML file into a config object.
+    ///
+    /// The file may include other files by a top-level `include` array, e.g.,
+    /// `include = ["../common/kernel.toml"]`, where the paths are relative to
+    /// the including file. The included files are loaded (recursively) and
+    /// merged in order before the items of the file itself, so the type
+    /// aliases they define can be used, and the items, rules and conditions
+    /// they define can be overridden by the including file (e.g., a platform
+    /// file changing a default value). A file included more than once is only
+    /// loaded the first time, and include cycles are rejected. Errors are
+    /// reported with the file and the include chain (see
+    /// [`ConfigErr::Included`]).
+    ///
+    /// The values are recorded as coming from the files defining them (with
+    /// the layer `file`, see [`ConfigItem::history`]), which can be renamed by
+    /// [`Config::set_layer`].
+    pub fn from_file(path: impl AsRef<Path>) -> ConfigResult<Self> {
+        Self::from_file_with(path, |_| {})
+    }
+
+    /// Same as [`Config::from_file`], but calls `on_read` with the path of
+    /// every file read, including the included ones.
+    pub fn from_file_with(
+        path: impl AsRef<Path>,
+        mut on_read: impl FnMut(&Path),
+    ) -> ConfigResult<Self> {
+        let mut loader = FileLoader {
+            chain: Vec::new(),
+            loaded: BTreeMap::new(),
+            result: Config::new(),
+            owners: BTreeMap::new(),
+            on_read: &mut on_read,
+        };
+        loader.load(path.as_ref())?;
+        Ok(loader.result)
+    }
+
+    /// Parses the items, tables and special tables of the document into
+    /// `self`, which may already contain type aliases (e.g., from the included
+    /// files).
+    fn parse_document(&mut self, table: &Table) -> ConfigResult<()> {
+        self.parse_type_aliases(table)?;
+        let aliases = self.types.clone();
+        let result = self;
         for (key, item) in table.iter() {
             match item {
+                Item::Value(_) if key == Self::INCLUDE_KEY => {}
                 Item::Value(val) => {
-                    result
-                        .global
-                        .insert(key.into(), ConfigItem::new_global(table, key, val)?);
+                    let item = ConfigItem::new_global(table, key, val, &aliases)?;
+                    result.global.insert(key.into(), item);
                 }
+                Item::Table(table) if result.parse_special_table(key, table)? => {}
                 Item::Table(table) => {
                     let table_name = key;
                     let comments = prefix_comments(table.decor());
                     let configs = result.new_table(key, comments.unwrap_or_default())?;
                     for (key, item) in table.iter() {
                         if let Item::Value(val) = item {
-                            configs
-                                .insert(key.into(), ConfigItem::new(table_name, table, key, val)?);
+                            let item = ConfigItem::new(table_name, table, key, val, &aliases)?;
+                            configs.insert(key.into(), item);
                         } else {
                             return Err(ConfigErr::InvalidValue);
                         }
@@ -232,26 +834,118 @@ impl Config {
                 }
             }
         }
-        Ok(result)
+        Ok(())
+    }
+
+    /// Parses the special tables (e.g., `[__rules__]`) into `self`, returns
+    /// `false` if the table is not a special one.
+    fn parse_special_table(&mut self, name: &str, table: &Table) -> ConfigResult<bool> {
+        if name == Self::TYPES_TABLE_NAME {
+            // already parsed before the items
+            return Ok(true);
+        }
+        if name != Self::RULES_TABLE_NAME && name != Self::DEPENDS_ON_TABLE_NAME {
+            return Ok(false);
+        }
+        for (key, item) in table.iter() {
+            let expr = item
+                .as_str()
+                .ok_or_else(|| ConfigErr::Other(format!("`{}.{}` must be a string", name, key)))?;
+            if name == Self::RULES_TABLE_NAME {
+                self.add_rule(key, expr)?;
+            } else {
+                self.add_dependency(key, expr)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Parses the type aliases in the `[__types__]` table of the document, which
+    /// are needed before parsing the items that refer to them.
+    fn parse_type_aliases(&mut self, root: &Table) -> ConfigResult<()> {
+        let Some(table) = root.get(Self::TYPES_TABLE_NAME).and_then(Item::as_table) else {
+            return Ok(());
+        };
+        let mut defs = BTreeMap::new();
+        let mut comments = BTreeMap::new();
+        for (key, item) in table.iter() {
+            let ty = item.as_str().ok_or_else(|| {
+                ConfigErr::Other(format!(
+                    "`{}.{}` must be a string",
+                    Self::TYPES_TABLE_NAME,
+                    key
+                ))
+            })?;
+            defs.insert(key.to_string(), ty.to_string());
+            let comment = prefix_comments(table.key(key).unwrap().leaf_decor());
+            if let Some(comment) = comment.filter(|c| !c.trim().is_empty()) {
+                comments.insert(key.to_string(), comment.to_string());
+            }
+        }
+        self.add_type_aliases(defs)?;
+        self.type_comments.extend(comments);
+        Ok(())
     }
 
     /// Dump the config into a string with the specified format.
     pub fn dump(&self, fmt: OutputFormat) -> ConfigResult<String> {
-        let mut output = Output::new(fmt);
+        self.dump_with_options(fmt, &DumpOptions::default())
+    }
+
+    /// Dump the config into the specified format with the options.
+    pub fn dump_with_options(&self, fmt: OutputFormat, opts: &DumpOptions) -> ConfigResult<String> {
+        if opts.strict {
+            self.check_strict()?;
+        }
+        let mut output = Output::new(fmt.clone(), opts.clone());
+        let aliases = || {
+            self.type_aliases()
+                .map(|(name, ty)| (name, ty, self.type_alias_comments(name).unwrap_or_default()))
+        };
+        // Type aliases are defined before the items in the Rust code, and
+        // written as a special table after the tables otherwise.
+        if let OutputFormat::Rust = fmt {
+            output.write_type_aliases(aliases())?;
+        }
+        // Disabled items are omitted in the generated Rust code.
+        let skip_disabled = matches!(fmt, OutputFormat::Rust);
         for (name, table, comments) in self.table_iter() {
+            if skip_disabled && self.table_disabled_by(name)?.is_some() {
+                continue;
+            }
             if name != Self::GLOBAL_TABLE_NAME {
-                output.table_begin(name, comments);
+                output.table_begin(name, comments)?;
             }
             for (key, item) in table.iter() {
-                if let Err(e) = output.write_item(item) {
-                    eprintln!("Dump config `{}` failed: {:?}", key, e);
+                if skip_disabled && self.disabled_by(item)?.is_some() {
+                    continue;
+                }
+                // the Rust code would not compile with mismatched lengths
+                if let OutputFormat::Rust = fmt {
+                    self.check_array_lens(item).map_err(|e| {
+                        ConfigErr::Other(format!("Config item `{}`: {}", item.item_name(), e))
+                    })?;
+                }
+                output.declare_item(item)?;
+                match output.write_item(item) {
+                    Err(e) if opts.strict => return Err(e),
+                    Err(e) => eprintln!("Dump config `{}` failed: {:?}", key, e),
+                    Ok(()) => {}
                 }
             }
             if name != Self::GLOBAL_TABLE_NAME {
                 output.table_end();
             }
         }
-        Ok(output.result().into())
+        if !matches!(fmt, OutputFormat::Rust) {
+            output.write_type_aliases(aliases())?;
+        }
+        // rules and conditions are kept for the configs built from the TOML output
+        if matches!(fmt, OutputFormat::Toml | OutputFormat::Schema) {
+            output.write_exprs(Self::RULES_TABLE_NAME, self.rules());
+            output.write_exprs(Self::DEPENDS_ON_TABLE_NAME, self.dependencies());
+        }
+        Ok(output.finish())
     }
 
     /// Dump the config into TOML format.
@@ -273,54 +967,764 @@ impl Config {
                 self.new_table(name, table_comments)?
             };
             for (key, item) in other_table.iter() {
-                if self_table.contains_key(key) {
-                    return Err(ConfigErr::Other(format!("Duplicate key `{}`", key)));
+                if let Some(self_item) = self_table.get(key) {
+                    let paths = [self_item, item].map(|i| i.source().and_then(ConfigSource::path));
+                    return Err(ConfigErr::Other(match paths {
+                        [Some(a), Some(b)] => format!(
+                            "Duplicate key `{}` (in {:?} and {:?})",
+                            item.item_name(),
+                            a,
+                            b
+                        ),
+                        _ => format!("Duplicate key `{}`", item.item_name()),
+                    }));
                 } else {
                     self_table.insert(key.into(), item.clone());
                 }
             }
         }
+        for (name, expr) in other.rules.iter() {
+            if self.rules.contains_key(name) {
+                return Err(ConfigErr::Other(format!("Duplicate rule `{}`", name)));
+            }
+            self.rules.insert(name.clone(), expr.clone());
+        }
+        for (name, expr) in other.depends_on.iter() {
+            if self.depends_on.contains_key(name) {
+                return Err(ConfigErr::Other(format!(
+                    "Duplicate condition of `{}`",
+                    name
+                )));
+            }
+            self.depends_on.insert(name.clone(), expr.clone());
+        }
+        for (name, ty) in other.types.iter() {
+            match self.types.get(name) {
+                Some(self_ty) if self_ty != ty => {
+                    return Err(ConfigErr::Other(format!("Duplicate type alias `{}`", name)));
+                }
+                _ => {
+                    self.types.insert(name.clone(), ty.clone());
+                }
+            }
+        }
+        for (name, comments) in other.type_comments.iter() {
+            self.type_comments
+                .entry(name.clone())
+                .or_insert_with(|| comments.clone());
+        }
         Ok(())
     }
 
     /// Update the values of `self` with the other config, if there is a key not
     /// found in `self`, skip it.
     ///
-    /// It returns two vectors of `ConfigItem`, the first contains the keys that
-    /// are included in `self` but not in `other`, the second contains the keys
-    /// that are included in `other` but not in `self`.
-    pub fn update(&mut self, other: &Self) -> ConfigResult<(Vec<ConfigItem>, Vec<ConfigItem>)> {
+    /// It returns a report of the warnings (see [`UpdateWarning`]), such as the
+    /// keys that are included in `self` but not in `other`, and the keys that
+    /// are included in `other` but not in `self`.
+    ///
+    /// If a key not found in `self` is an old name of an item (see
+    /// [`ConfigItem::renamed_from`]), its value is migrated to that item,
+    /// unless the item is also set in `other`. If a value in `other` does not
+    /// match the type of the item, it is coerced to the type if possible (e.g.,
+    /// the type annotation in `other` is ignored), otherwise the value is
+    /// ignored.
+    pub fn update(&mut self, other: &Self) -> ConfigResult<UpdateReport> {
+        let mut report = UpdateReport::default();
         let mut touched = BTreeSet::new(); // included in both `self` and `other`
         let mut extra = Vec::new(); // included in `other` but not in `self`
 
         for other_item in other.iter() {
-            let table_name = other_item.table_name.clone();
-            let key = other_item.key.clone();
-            let self_table = if let Some(table) = self.table_at_mut(&table_name) {
-                table
+            if let Some(self_item) = self.config_at_mut(&other_item.table_name, &other_item.key) {
+                self_item.update_coerced(other_item, &mut report)?;
+                touched.insert(self_item.item_name());
+            } else {
+                extra.push(other_item);
+            }
+        }
+
+        // migrate the values of renamed items, unless the new ones are set
+        for other_item in extra {
+            let old_name = other_item.item_name();
+            match self.renamed_item_mut(&old_name) {
+                Some(self_item) if !touched.contains(&self_item.item_name()) => {
+                    report.push(UpdateWarning::Migrated {
+                        from: old_name,
+                        to: self_item.item_name(),
+                    });
+                    self_item.update_coerced(other_item, &mut report)?;
+                    touched.insert(self_item.item_name());
+                }
+                _ => report.push(UpdateWarning::Extra { item: old_name }),
+            }
+        }
+
+        // included in `self` but not in `other`, disabled items and items whose
+        // values are chosen from the variants later are not reported
+        for item in self.iter() {
+            let unresolved = item.selector.is_some() && item.variant.is_none();
+            if !touched.contains(&item.item_name())
+                && !unresolved
+                && self.disabled_by(item)?.is_none()
+            {
+                report.push(UpdateWarning::Untouched {
+                    item: item.item_name(),
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// Overlays the other config on top of `self`.
+    ///
+    /// The values in `other` override the values in `self`, and the source of
+    /// each overridden value is appended to the provenance chain of the item
+    /// (see [`ConfigItem::history`]). Unlike [`Config::update`], if there is a
+    /// key not found in `self` or a value does not match the type, return an
+    /// error. Old names of items are migrated as in [`Config::update`], and
+    /// reported in the returned report. On error, `self` is left unchanged.
+    ///
+    /// Multiple layers can be stacked by calling this method repeatedly, the
+    /// later layers override the earlier ones.
+    pub fn overlay(&mut self, other: &Self) -> ConfigResult<UpdateReport> {
+        let mut report = UpdateReport::default();
+        let mut result = self.clone();
+        for other_item in other.iter() {
+            let table_name = &other_item.table_name;
+            let self_item = if result.config_at(table_name, &other_item.key).is_some() {
+                result.config_at_mut(table_name, &other_item.key)
             } else {
-                extra.push(other_item.clone());
+                let self_item = result.renamed_item_mut(&other_item.item_name());
+                if let Some(self_item) = &self_item {
+                    report.push(UpdateWarning::Migrated {
+                        from: other_item.item_name(),
+                        to: self_item.item_name(),
+                    });
+                }
+                self_item
+            };
+            let self_item = self_item.ok_or_else(|| {
+                ConfigErr::Other(format!(
+                    "Config item `{}` not found in the lower layers",
+                    other_item.item_name()
+                ))
+            })?;
+            self_item.update_with(other_item)?;
+        }
+        *self = result;
+        Ok(report)
+    }
+
+    /// Overrides the values of config items with environment variables.
+    ///
+    /// For each config item, `getenv` is called with the name of the variable
+    /// (see [`ConfigItem::env_name`]). If it returns a string, the string is
+    /// parsed as a TOML value and updates the item with type checking. The
+    /// string is taken as a plain string instead if it is not a valid TOML
+    /// value or does not match the type, e.g., `1234` for a `str` item.
+    ///
+    /// It returns the names of the items that have been overridden, or an
+    /// error if a value is invalid or multiple items map to the same variable
+    /// (e.g., `a-b` and `a_b`). On error, `self` is left unchanged.
+    pub fn apply_env(
+        &mut self,
+        prefix: &str,
+        mut getenv: impl FnMut(&str) -> Option<String>,
+    ) -> ConfigResult<Vec<String>> {
+        let mut names = BTreeMap::new();
+        for item in self.iter() {
+            let name = item.env_name(prefix);
+            if let Some(other) = names.insert(name.clone(), item.item_name()) {
+                return Err(ConfigErr::Other(format!(
+                    "Config items `{}` and `{}` map to the same environment variable `{}`",
+                    other,
+                    item.item_name(),
+                    name
+                )));
+            }
+        }
+
+        let mut overridden = Vec::new();
+        let mut result = self.clone();
+        for item in result.items_mut() {
+            let name = item.env_name(prefix);
+            if let Some(var) = getenv(&name) {
+                let source = ConfigSource::new("environment", Some(&name));
+                let plain = || ConfigValue::from_raw_value(&Value::from(var.as_str()));
+                let res = match ConfigValue::new(&var) {
+                    Ok(value) => match item.update_from(value, source.clone()) {
+                        Err(ConfigErr::ValueTypeMismatch) => item.update_from(plain()?, source),
+                        res => res,
+                    },
+                    Err(_) => item.update_from(plain()?, source),
+                };
+                res.map_err(|e| {
+                    ConfigErr::Other(format!(
+                        "Invalid value `{}` of environment variable `{}`: {}",
+                        var, name, e
+                    ))
+                })?;
+                overridden.push(item.item_name());
+            }
+        }
+        *self = result;
+        Ok(overridden)
+    }
+
+    /// Adds a validation rule to the config.
+    ///
+    /// The rule is an expression over config items that must evaluate to
+    /// `true`. Items are referenced by their names (`key` for the global table
+    /// and `table.key` for others). Integers, floats, strings and booleans, arithmetic,
+    /// bitwise, comparison and logical operators, indexing (`a[0]`) and the
+    /// functions `len(x)`, `min(..)`, `max(..)` and `no_overlap(regions)` are
+    /// supported. Since keys may contain `-`, the subtraction operator must be
+    /// surrounded by spaces.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use axconfig_gen::Config;
+    /// let mut config = Config::from_toml("[plat]\nbase = 0x1000\nsize = 0x2000").unwrap();
+    /// config.add_rule("no-overflow", "plat.base + plat.size <= 0xffff_ffff").unwrap();
+    /// assert!(config.check_rules().is_ok());
+    /// ```
+    pub fn add_rule(&mut self, name: &str, expr: &str) -> ConfigResult<()> {
+        if self.rules.contains_key(name) {
+            return Err(ConfigErr::Other(format!("Duplicate rule `{}`", name)));
+        }
+        self.rules.insert(name.into(), Expr::parse(expr)?);
+        Ok(())
+    }
+
+    /// Returns the iterator of all validation rules.
+    ///
+    /// The iterator returns a tuple of rule name and expression.
+    pub fn rules(&self) -> impl Iterator<Item = (&str, String)> {
+        self.rules.iter().map(|(k, v)| (k.as_str(), v.to_string()))
+    }
+
+    /// Evaluates all validation rules against the current values.
+    ///
+    /// It should be called after all the specifications are merged and all the
+    /// values are updated. If any rule fails, return an error listing the
+    /// failed rules and the values of the involved items.
+    ///
+    /// The lengths of fixed-size arrays referring to other items (e.g.,
+    /// `[uint; smp]`) are also checked here, since the referred values may be
+    /// updated after parsing, and so are the conditions (see
+    /// [`Config::disabled_by`]), which must evaluate to booleans.
+    pub fn check_rules(&self) -> ConfigResult<()> {
+        let mut errors = Vec::new();
+        // invalid conditions are reported once, and do not disable the items
+        for name in self.depends_on.keys() {
+            if let Err(e) = self.condition_fails(name) {
+                errors.push(e.to_string());
+            }
+        }
+        for item in self.iter() {
+            if matches!(self.disabled_by(item), Ok(Some(_))) {
                 continue;
+            }
+            if let Err(e) = self.check_array_lens(item) {
+                errors.push(format!("Config item `{}`: {}", item.item_name(), e));
+            }
+        }
+        for (name, expr) in self.rules.iter() {
+            match self.eval(expr) {
+                Ok(ExprValue::Bool(true)) => continue,
+                Ok(ExprValue::Bool(false)) => {
+                    let mut msg = format!("Rule `{}` failed: {}", name, expr);
+                    for item in expr
+                        .idents()
+                        .into_iter()
+                        .filter_map(|n| self.item_by_name(n))
+                    {
+                        let value = item.value().to_toml_value().replace('\n', "\n    ");
+                        msg += &format!("\n    {} = {}", item.item_name(), value);
+                    }
+                    errors.push(msg);
+                }
+                Ok(v) => errors.push(format!(
+                    "Rule `{}` must evaluate to a boolean, got `{}`",
+                    name, v
+                )),
+                Err(e) => errors.push(format!("Rule `{}` failed: {}", name, e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErr::Other(errors.join("\n")))
+        }
+    }
+
+    /// Adds a type alias, which can be used in the types of the items added
+    /// afterwards (e.g., `[region]`).
+    ///
+    /// The alias name must not be a built-in type name, and `ty` may refer to
+    /// the existing aliases.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use axconfig_gen::{Config, ConfigType};
+    /// let mut config = Config::new();
+    /// config.add_type_alias("region", "(uint, uint)").unwrap();
+    /// let ty = config.type_alias("region").unwrap();
+    /// assert_eq!(ty, &ConfigType::new("(uint, uint)").unwrap());
+    /// assert!(config.add_type_alias("uint", "int").is_err());
+    /// ```
+    pub fn add_type_alias(&mut self, name: &str, ty: &str) -> ConfigResult<()> {
+        self.add_type_aliases(BTreeMap::from([(name.into(), ty.into())]))
+    }
+
+    /// Adds the type aliases, which may refer to each other.
+    fn add_type_aliases(&mut self, defs: BTreeMap<String, String>) -> ConfigResult<()> {
+        if let Some(name) = defs.keys().find(|n| self.types.contains_key(*n)) {
+            return Err(ConfigErr::Other(format!("Duplicate type alias `{}`", name)));
+        }
+        // the existing aliases are resolved again along with the new ones
+        let mut all = self
+            .types
+            .iter()
+            .map(|(name, ty)| (name.clone(), ty.to_string()))
+            .collect::<BTreeMap<_, _>>();
+        all.extend(defs);
+        self.types = resolve_aliases(&all)?;
+        Ok(())
+    }
+
+    /// Returns the type that the type alias refers to.
+    pub fn type_alias(&self, name: &str) -> Option<&ConfigType> {
+        self.types.get(name)
+    }
+
+    /// Returns the comments of the type alias, which are written to the TOML
+    /// output and become the doc comments of the type alias in the Rust code.
+    pub fn type_alias_comments(&self, name: &str) -> Option<&str> {
+        self.type_comments.get(name).map(|s| s.as_str())
+    }
+
+    /// Returns the iterator of all type aliases.
+    ///
+    /// The iterator returns a tuple of alias name and the type it refers to.
+    pub fn type_aliases(&self) -> impl Iterator<Item = (&str, &ConfigType)> {
+        self.types.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Adds a condition to a table or an item.
+    ///
+    /// `name` is a table name or an item name (`key` or `table.key`), and
+    /// `expr` is an expression over config items (see [`Config::add_rule`] for
+    /// the syntax). The table or item is disabled if the expression evaluates
+    /// to `false`.
+    pub fn add_dependency(&mut self, name: &str, expr: &str) -> ConfigResult<()> {
+        if self.depends_on.contains_key(name) {
+            return Err(ConfigErr::Other(format!(
+                "Duplicate condition of `{}`",
+                name
+            )));
+        }
+        self.depends_on.insert(name.into(), Expr::parse(expr)?);
+        Ok(())
+    }
+
+    /// Returns the iterator of all conditions.
+    ///
+    /// The iterator returns a tuple of table or item name and expression.
+    pub fn dependencies(&self) -> impl Iterator<Item = (&str, String)> {
+        self.depends_on
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.to_string()))
+    }
+
+    /// Returns the condition that disables the item, or `None` if the item is
+    /// enabled.
+    ///
+    /// An item is disabled if the condition of either its table or itself
+    /// evaluates to `false`. Disabled items are omitted in the generated Rust
+    /// code, and are not reported as untouched by [`Config::update`].
+    pub fn disabled_by(&self, item: &ConfigItem) -> ConfigResult<Option<String>> {
+        if let Some(cond) = self.table_disabled_by(&item.table_name)? {
+            return Ok(Some(cond));
+        }
+        self.condition_fails(&item.item_name())
+    }
+
+    fn table_disabled_by(&self, name: &str) -> ConfigResult<Option<String>> {
+        if name == Self::GLOBAL_TABLE_NAME {
+            return Ok(None);
+        }
+        self.condition_fails(name)
+    }
+
+    fn condition_fails(&self, name: &str) -> ConfigResult<Option<String>> {
+        let Some(expr) = self.depends_on.get(name) else {
+            return Ok(None);
+        };
+        let value = self
+            .eval(expr)
+            .map_err(|e| ConfigErr::Other(format!("Condition of `{}` failed: {}", name, e)))?;
+        match value {
+            ExprValue::Bool(true) => Ok(None),
+            ExprValue::Bool(false) => Ok(Some(expr.to_string())),
+            v => Err(ConfigErr::Other(format!(
+                "Condition of `{}` must evaluate to a boolean, got `{}`",
+                name, v
+            ))),
+        }
+    }
+
+    /// Resolves the value variants of the config items.
+    ///
+    /// For each item with variants (e.g., `{ default = 100, aarch64 = 1000 }`
+    /// annotated with `by("arch")`), the variant named after the value of the
+    /// selector item is chosen, or the `default` one if none matches. String
+    /// values are used as is, and others in their TOML form (e.g., `true`).
+    /// The chosen variant is recorded in the provenance chain of the item (see
+    /// [`ConfigItem::history`]). Items already resolved are skipped.
+    ///
+    /// It should be called after all specifications are merged and all the
+    /// other configs (e.g., the old config and the environment variables) are
+    /// applied, since the selector may come from another specification or be
+    /// changed by them. Items whose values are set by these configs take the
+    /// values as is, and no longer have variants.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use axconfig_gen::Config;
+    /// let spec = r#"
+    /// arch = "aarch64"
+    /// ticks-per-sec = { default = 100, aarch64 = 1000 } # uint, by("arch")
+    /// "#;
+    /// let mut config = Config::from_toml(spec).unwrap();
+    /// config.resolve_variants().unwrap();
+    /// let item = config.config_at(Config::GLOBAL_TABLE_NAME, "ticks-per-sec").unwrap();
+    /// assert_eq!(item.value().as_u64(), Some(1000));
+    /// assert_eq!(item.variant(), Some("aarch64"));
+    /// ```
+    pub fn resolve_variants(&mut self) -> ConfigResult<()> {
+        let mut chosen = Vec::new();
+        for item in self.iter().filter(|item| item.variant.is_none()) {
+            let Some(name) = &item.selector else {
+                continue;
+            };
+            let selector = self.item_by_name(name).ok_or_else(|| {
+                ConfigErr::Other(format!(
+                    "Selector `{}` of config item `{}` not found",
+                    name,
+                    item.item_name()
+                ))
+            })?;
+            if selector.selector.is_some() {
+                return Err(ConfigErr::Other(format!(
+                    "Selector `{}` of config item `{}` cannot have value variants",
+                    name,
+                    item.item_name()
+                )));
+            }
+            let key = match selector.value.as_str() {
+                Some(s) => s.to_string(),
+                None => selector.value.to_toml_value(),
             };
+            let variant = item.variants.iter().find(|(name, _)| *name == key);
+            let (variant, value) = variant.unwrap_or(&item.variants[0]);
+            let (table, key) = (item.table_name.clone(), item.key.clone());
+            chosen.push((table, key, variant.clone(), value.clone()));
+        }
+        for (table, key, variant, value) in chosen {
+            let item = self.config_at_mut(&table, &key).unwrap();
+            item.value.update(value)?;
+            let source = ConfigSource::new("variant", Some(&variant));
+            item.history.push((source, item.value.clone()));
+            item.variant = Some(variant);
+        }
+        Ok(())
+    }
 
-            if let Some(self_item) = self_table.get_mut(&key) {
-                self_item.value.update(other_item.value.clone())?;
-                touched.insert(self_item.item_name());
+    fn check_array_lens(&self, item: &ConfigItem) -> ConfigResult<()> {
+        let Some(ty) = item.value.ty() else {
+            return Ok(());
+        };
+        let len_of = |name: &str| {
+            let len = self
+                .item_by_name(name)
+                .filter(|len| {
+                    matches!(
+                        len.value.ty().map(ConfigType::resolved),
+                        Some(ConfigType::Uint)
+                    )
+                })
+                .and_then(|len| len.value.as_u64());
+            let len = len.ok_or_else(|| {
+                ConfigErr::Other(format!("Array length `{}` is not a `uint` item", name))
+            })?;
+            Ok(len as usize)
+        };
+        check_array_lens(item.value.raw(), ty, &len_of)
+    }
+
+    fn item_by_name(&self, name: &str) -> Option<&ConfigItem> {
+        let (table, key) = name
+            .split_once('.')
+            .unwrap_or((Self::GLOBAL_TABLE_NAME, name));
+        self.config_at(table, key)
+    }
+
+    /// Returns the item that was named `name` (see [`ConfigItem::renamed_from`]).
+    fn renamed_item_mut(&mut self, name: &str) -> Option<&mut ConfigItem> {
+        [&mut self.global]
+            .into_iter()
+            .chain(self.tables.values_mut())
+            .flat_map(|t| t.values_mut())
+            .find(|item| item.renamed_from_name(name))
+    }
+
+    fn eval(&self, expr: &Expr) -> ConfigResult<ExprValue> {
+        expr.eval(&|name| {
+            let item = self
+                .item_by_name(name)
+                .ok_or_else(|| ConfigErr::Other(format!("config item `{}` not found", name)))?;
+            ExprValue::from_config_value(item.value())
+        })
+    }
+
+    /// Returns a minimal config that only contains the items in `self` whose
+    /// values differ from the default values in `spec`.
+    ///
+    /// Items that are not found in `spec` are always included, and items with
+    /// value variants in `spec` are only included if their values are set
+    /// explicitly. The comments of the items and tables are kept. Updating
+    /// `spec` with the result (see [`Config::update`]) gives back the full
+    /// config, where the items left out are reported as
+    /// [`UpdateWarning::Untouched`].
+    pub fn diff(&self, spec: &Self) -> ConfigResult<Self> {
+        let mut result = Self::new();
+        // keep the aliases the types of the items may refer to
+        result.types = self.types.clone();
+        result.type_comments = self.type_comments.clone();
+        for item in self.iter() {
+            if let Some(default) = spec.config_at(&item.table_name, &item.key) {
+                // values chosen from the variants follow the selector, while
+                // the explicit ones are always kept
+                if default.selector.is_some() {
+                    if item.selector.is_some() {
+                        continue;
+                    }
+                } else if item.value.value_eq(&default.value) {
+                    continue;
+                }
+            }
+            let table_name = item.table_name.as_str();
+            let table = if let Some(table) = result.table_at_mut(table_name) {
+                table
             } else {
-                extra.push(other_item.clone());
+                let comments = self.table_comments_at(table_name).unwrap_or_default();
+                result.new_table(table_name, comments)?
+            };
+            table.insert(item.key.clone(), item.clone());
+        }
+        Ok(result)
+    }
+}
+
+/// Parses the value variants of an item, the `default` one comes first.
+fn parse_variants<'a>(
+    variants: impl Iterator<Item = (&'a str, &'a Value)>,
+    ty: &ConfigType,
+    constraints: &[Constraint],
+) -> ConfigResult<Vec<(String, ConfigValue)>> {
+    let mut result = Vec::new();
+    for (name, value) in variants {
+        let mut value = ConfigValue::from_raw_value_type(value, ty.clone())?;
+        value.set_constraints(constraints.to_vec())?;
+        if name != "default" {
+            result.push((name.to_string(), value));
+        } else if result.first().is_some_and(|(n, _)| n == "default") {
+            return Err(ConfigErr::Other("Duplicate variant `default`".into()));
+        } else {
+            result.insert(0, (name.to_string(), value));
+        }
+    }
+    if !result.first().is_some_and(|(n, _)| n == "default") {
+        return Err(ConfigErr::Other(
+            "Value variants must contain a `default` one".into(),
+        ));
+    }
+    Ok(result)
+}
+
+/// The type aliases visible to a loaded file, and the files it includes
+/// (recursively, in canonical form, including itself).
+type Loaded = (BTreeMap<String, ConfigType>, BTreeSet<PathBuf>);
+
+/// The state of loading a file and the files it includes.
+struct FileLoader<'a> {
+    /// The files being loaded, the last one is the current file.
+    chain: Vec<PathBuf>,
+    /// The files that have been loaded in canonical form.
+    loaded: BTreeMap<PathBuf, Loaded>,
+    /// The items, rules and conditions of all the files loaded so far.
+    result: Config,
+    /// The files defining the items (`key`), rules (`rule`) and conditions
+    /// (`condition`) in `result`.
+    owners: BTreeMap<(&'static str, String), PathBuf>,
+    on_read: &'a mut dyn FnMut(&Path),
+}
+
+fn canonical_of(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl FileLoader<'_> {
+    fn load(&mut self, path: &Path) -> ConfigResult<Loaded> {
+        let canonical = canonical_of(path);
+        if let Some(pos) = self.chain.iter().position(|p| canonical_of(p) == canonical) {
+            let cycle = self.chain[pos..].iter().map(|p| p.as_path());
+            let cycle = cycle.chain([path]).map(|p| format!("{:?}", p));
+            let cycle = cycle.collect::<Vec<_>>();
+            return Err(ConfigErr::Other(format!(
+                "Include cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        if let Some(loaded) = self.loaded.get(&canonical) {
+            // already merged into the result
+            return Ok(loaded.clone());
+        }
+        self.chain.push(path.to_path_buf());
+        let res = self.load_current();
+        self.chain.pop();
+        let (types, mut includes) = res?;
+        includes.insert(canonical.clone());
+        self.loaded
+            .insert(canonical, (types.clone(), includes.clone()));
+        Ok((types, includes))
+    }
+
+    /// Loads the last file in the chain, and returns the type aliases it
+    /// defines and the files it includes.
+    fn load_current(&mut self) -> ConfigResult<Loaded> {
+        let path = self.chain.last().unwrap().clone();
+        // errors in the included files are reported with the include chain
+        let chain = self.chain.iter().rev().map(|p| p.display().to_string());
+        let chain = chain.collect::<Vec<_>>();
+        let wrap = |err| ConfigErr::Included {
+            chain: chain.clone(),
+            err: Box::new(err),
+        };
+
+        let toml = std::fs::read_to_string(&path).map_err(|e| {
+            wrap(ConfigErr::Other(format!(
+                "Failed to read config file: {}",
+                e
+            )))
+        })?;
+        (self.on_read)(&path);
+        let doc = toml.parse::<DocumentMut>().map_err(|e| wrap(e.into()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut types = Config::new();
+        let mut includes = BTreeSet::new();
+        for include in include_paths(&doc).map_err(wrap)? {
+            let (included_types, included) = self.load(&base.join(include))?;
+            let mut included_config = Config::new();
+            included_config.types = included_types;
+            types.merge(&included_config).map_err(wrap)?;
+            includes.extend(included);
+        }
+        let mut own = Config::new();
+        own.types = types.types;
+        own.parse_document(doc.as_table()).map_err(wrap)?;
+        own.set_source(ConfigSource::new("file", Some(&path.display().to_string())));
+        let types = own.types.clone();
+        self.merge_own(own, &path, &includes).map_err(wrap)?;
+        Ok((types, includes))
+    }
+
+    /// Merges the config of the file `path` into the result. The items, rules
+    /// and conditions already defined by the files it includes are overridden,
+    /// others are duplicates.
+    fn merge_own(
+        &mut self,
+        mut own: Config,
+        path: &Path,
+        includes: &BTreeSet<PathBuf>,
+    ) -> ConfigResult<()> {
+        let check_owner = |kind: &'static str, name: &str, what: String| match self
+            .owners
+            .get(&(kind, name.to_string()))
+        {
+            Some(owner) if includes.contains(&canonical_of(owner)) => Ok(true),
+            Some(owner) => Err(ConfigErr::Other(format!(
+                "Duplicate {} (in {:?} and {:?})",
+                what, owner, path
+            ))),
+            None => Ok(false),
+        };
+        let mut overridden = Vec::new();
+        for item in own.iter() {
+            let name = item.item_name();
+            if check_owner("key", &name, format!("key `{}`", name))? {
+                overridden.push((item.table_name.clone(), item.key.clone()));
+            }
+        }
+        for name in own.rules.keys() {
+            if check_owner("rule", name, format!("rule `{}`", name))? {
+                self.result.rules.remove(name);
+            }
+        }
+        for name in own.depends_on.keys() {
+            if check_owner("condition", name, format!("condition of `{}`", name))? {
+                self.result.depends_on.remove(name);
             }
         }
 
-        // included in `self` but not in `other`
-        let untouched = self
-            .iter()
-            .filter(|item| !touched.contains(&item.item_name()))
-            .cloned()
+        for (table, key) in overridden {
+            let item = own.table_at_mut(&table).unwrap().remove(&key).unwrap();
+            let base = self.result.config_at_mut(&table, &key).unwrap();
+            base.update_with(&item).map_err(|e| {
+                ConfigErr::Other(format!(
+                    "Config item `{}` from {:?} cannot be overridden: {}",
+                    item.item_name(),
+                    self.owners[&("key", item.item_name())],
+                    e
+                ))
+            })?;
+        }
+        let owned = own.iter().map(|item| ("key", item.item_name()));
+        let owned = owned
+            .chain(own.rules.keys().map(|name| ("rule", name.clone())))
+            .chain(
+                own.depends_on
+                    .keys()
+                    .map(|name| ("condition", name.clone())),
+            )
             .collect::<Vec<_>>();
-        Ok((untouched, extra))
+        self.result.merge(&own)?;
+        for key in owned {
+            self.owners.insert(key, path.to_path_buf());
+        }
+        Ok(())
     }
 }
 
+/// Returns the paths in the `include` directive of the document.
+fn include_paths(doc: &DocumentMut) -> ConfigResult<Vec<&str>> {
+    let Some(item) = doc.get(Config::INCLUDE_KEY) else {
+        return Ok(Vec::new());
+    };
+    let err = || {
+        ConfigErr::Other(format!(
+            "`{}` must be an array of strings",
+            Config::INCLUDE_KEY
+        ))
+    };
+    let arr = item.as_array().ok_or_else(err)?;
+    arr.iter().map(|v| v.as_str().ok_or_else(err)).collect()
+}
+
 fn prefix_comments(decor: &Decor) -> Option<&str> {
     decor.prefix().and_then(|s| s.as_str())
 }
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use toml_edit::Value;
+
+use crate::ty::split_top_level;
+use crate::{ConfigErr, ConfigResult, ConfigType, Constraint};
+
+/// The annotation of a config item, i.e., the comment following the value
+/// (e.g., `# uint, range(1..=256), renamed_from("nr-cpus")`), optionally
+/// followed by the inline documentation after a `;`.
+///
+/// The first part is the type, and the rest are constraints or metadata:
+/// `deprecated`, `deprecated("message")`, `renamed_from("old-key", ...)` and
+/// `by("item")` for the items with value variants.
+pub(super) struct Annotation {
+    pub ty: ConfigType,
+    pub constraints: Vec<Constraint>,
+    pub deprecated: Option<String>,
+    pub renamed_from: Vec<String>,
+    pub by: Option<String>,
+}
+
+impl Annotation {
+    /// Parses the trailing comment of a config item, returns the annotation
+    /// (if any) and the inline documentation.
+    ///
+    /// The comment has the form `# annotation; documentation`, where both
+    /// parts are optional. If the comment starts with `#:`, the first part
+    /// must be a valid annotation. Otherwise, the whole comment is treated as
+    /// documentation if it does not start with a valid type and is clearly
+    /// prose (see [`is_prose`]), e.g., `# number of CPUs`, so mistyped types
+    /// such as `# unit` are still errors. Type aliases in the type are
+    /// resolved by `aliases`.
+    pub fn parse_comment(
+        comment: &str,
+        aliases: &BTreeMap<String, ConfigType>,
+    ) -> ConfigResult<(Option<Self>, String)> {
+        let body = comment.trim_start_matches('#');
+        let (explicit, body) = match body.strip_prefix(':') {
+            Some(body) => (true, body),
+            None => (false, body),
+        };
+        let annotation = split_top_level(body, ';')[0];
+        let doc = body[annotation.len()..]
+            .strip_prefix(';')
+            .unwrap_or_default();
+        if annotation.trim().is_empty() {
+            return Ok((None, doc.trim().into()));
+        }
+        let ty = split_top_level(annotation, ',')[0];
+        if !explicit
+            && ConfigType::new_with_aliases(ty, aliases).is_err()
+            && is_prose(body, aliases)
+        {
+            return Ok((None, body.trim().into()));
+        }
+        Ok((Some(Self::parse(annotation, aliases)?), doc.trim().into()))
+    }
+
+    pub fn parse(annotation: &str, aliases: &BTreeMap<String, ConfigType>) -> ConfigResult<Self> {
+        let mut parts = split_top_level(annotation, ',').into_iter();
+        let mut result = Self {
+            ty: ConfigType::new_with_aliases(parts.next().unwrap(), aliases)?,
+            constraints: Vec::new(),
+            deprecated: None,
+            renamed_from: Vec::new(),
+            by: None,
+        };
+        for part in parts.map(str::trim) {
+            if part == "deprecated" {
+                result.deprecated = Some(String::new());
+            } else if let Some(args) = string_args(part, "deprecated") {
+                match args?.as_slice() {
+                    [msg] => result.deprecated = Some(msg.clone()),
+                    _ => return Err(invalid_metadata(part)),
+                }
+            } else if let Some(args) = string_args(part, "renamed_from") {
+                result.renamed_from.extend(args?);
+            } else if let Some(args) = string_args(part, "by") {
+                match args?.as_slice() {
+                    [name] => result.by = Some(name.clone()),
+                    _ => return Err(invalid_metadata(part)),
+                }
+            } else {
+                result.constraints.push(Constraint::new(part)?);
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Returns whether the comment is plain text rather than a mistyped annotation,
+/// i.e., it has several words, and the first word is neither a type nor
+/// contains the brackets of types.
+fn is_prose(comment: &str, aliases: &BTreeMap<String, ConfigType>) -> bool {
+    let mut words = comment.split_whitespace();
+    let first = words.next().unwrap_or_default();
+    words.next().is_some()
+        && !first.contains(['[', '(', '<'])
+        && ConfigType::new_with_aliases(first.trim_end_matches([',', ';']), aliases).is_err()
+}
+
+/// Returns the trailing comment for the inline documentation of an item
+/// without annotation, which is parsed back as documentation.
+pub(crate) fn doc_comment(doc: &str) -> String {
+    match Annotation::parse_comment(&format!("# {}", doc), &BTreeMap::new()) {
+        Ok((None, parsed)) if parsed == doc => format!("# {}", doc),
+        _ => format!("#; {}", doc),
+    }
+}
+
+/// Parses the string arguments of `name("arg1", "arg2", ...)`, returns `None`
+/// if `s` is not a call of `name`.
+fn string_args(s: &str, name: &str) -> Option<ConfigResult<Vec<String>>> {
+    let args = s.strip_prefix(name)?.trim_start().strip_prefix('(')?;
+    let args = args.strip_suffix(')')?;
+    let parse = || match format!("[{}]", args).parse::<Value>() {
+        Ok(Value::Array(arr)) if !arr.is_empty() => arr
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .map(String::from)
+                    .ok_or_else(|| invalid_metadata(s))
+            })
+            .collect(),
+        _ => Err(invalid_metadata(s)),
+    };
+    Some(parse())
+}
+
+fn invalid_metadata(s: &str) -> ConfigErr {
+    ConfigErr::Other(format!("Invalid annotation `{}`", s))
+}
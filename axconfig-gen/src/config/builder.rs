@@ -0,0 +1,103 @@
+use super::Config;
+use crate::{ConfigErr, ConfigResult, ConfigType, ConfigValue};
+
+/// A builder to construct a [`Config`] programmatically.
+///
+/// Items are added to the current table, which is the global table at first
+/// and is switched by [`table`](Self::table). The invariants are the same as
+/// those of [`Config::add_table`] and [`Config::insert`], and the first error
+/// is returned by [`build`](Self::build).
+///
+/// # Example
+///
+/// ```
+/// # use axconfig_gen::Config;
+/// let config = Config::builder()
+///     .item("smp", "4", Some("uint"), "Number of CPUs.")
+///     .table("platform", "Platform configs")
+///     .item("phys-memory-size", "0x800_0000", Some("uint"), "")
+///     .item("family", "\"x86-pc\"", None, "")
+///     .rule("smp-limit", "smp <= 8")
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.get::<usize>("platform.phys-memory-size").unwrap(), 0x800_0000);
+///
+/// let err = Config::builder().table("$GLOBAL", "").build();
+/// assert!(err.is_err());
+/// ```
+pub struct ConfigBuilder {
+    config: Config,
+    table: String,
+    err: Option<ConfigErr>,
+}
+
+impl ConfigBuilder {
+    /// Creates a new builder with an empty config.
+    pub fn new() -> Self {
+        Self {
+            config: Config::new(),
+            table: Config::GLOBAL_TABLE_NAME.into(),
+            err: None,
+        }
+    }
+
+    fn with(mut self, f: impl FnOnce(&mut Config, &str) -> ConfigResult<()>) -> Self {
+        if self.err.is_none() {
+            if let Err(e) = f(&mut self.config, &self.table) {
+                self.err = Some(e);
+            }
+        }
+        self
+    }
+
+    /// Adds a new table and makes it the current table.
+    pub fn table(mut self, name: &str, comments: &str) -> Self {
+        self = self.with(|config, _| config.add_table(name, comments));
+        self.table = name.into();
+        self
+    }
+
+    /// Adds a config item to the current table.
+    ///
+    /// `value` is a TOML-formatted string, and `ty` is the type name (e.g.,
+    /// `uint`, `[(uint, uint)]`). The type is inferred on dump if not
+    /// specified.
+    pub fn item(self, key: &str, value: &str, ty: Option<&str>, comments: &str) -> Self {
+        self.with(|config, table| {
+            let value = ConfigValue::new(value)?;
+            let ty = ty
+                .map(|ty| ConfigType::new_with_aliases(ty, &config.types))
+                .transpose()?;
+            config.insert(table, key, value, ty, comments)
+        })
+    }
+
+    /// Adds a type alias (see [`Config::add_type_alias`]).
+    pub fn type_alias(self, name: &str, ty: &str) -> Self {
+        self.with(|config, _| config.add_type_alias(name, ty))
+    }
+
+    /// Adds a validation rule (see [`Config::add_rule`]).
+    pub fn rule(self, name: &str, expr: &str) -> Self {
+        self.with(|config, _| config.add_rule(name, expr))
+    }
+
+    /// Adds a condition to a table or an item (see [`Config::add_dependency`]).
+    pub fn depends_on(self, name: &str, expr: &str) -> Self {
+        self.with(|config, _| config.add_dependency(name, expr))
+    }
+
+    /// Returns the constructed config, or the first error that occurred.
+    pub fn build(self) -> ConfigResult<Config> {
+        match self.err {
+            Some(e) => Err(e),
+            None => Ok(self.config),
+        }
+    }
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+use super::{parse_variants, Config, ConfigItem};
+use crate::{ConfigErr, ConfigResult, ConfigType, ConfigValue, Constraint};
+
+impl ConfigItem {
+    fn from_schema(
+        table_name: &str,
+        key: &str,
+        schema: &Table,
+        aliases: &BTreeMap<String, ConfigType>,
+    ) -> ConfigResult<Self> {
+        let field_err = |field: &str, expect: &str| {
+            ConfigErr::Other(format!(
+                "Field `{}` of schema item `{}` must be {}",
+                field, key, expect
+            ))
+        };
+        let mut ty = None;
+        let mut default = None;
+        let mut comments = String::new();
+        let mut constraints = Vec::new();
+        let mut deprecated = None;
+        let mut renamed_from = Vec::new();
+        let mut selector = None;
+        let mut variants = None;
+        for (field, item) in schema.iter() {
+            match field {
+                "type" => {
+                    let s = item.as_str().ok_or_else(|| field_err(field, "a string"))?;
+                    ty = Some(ConfigType::new_with_aliases(s, aliases)?);
+                }
+                "default" => {
+                    default = Some(item.as_value().ok_or_else(|| field_err(field, "a value"))?);
+                }
+                "description" => {
+                    let s = item.as_str().ok_or_else(|| field_err(field, "a string"))?;
+                    comments = description_to_comments(s);
+                }
+                "constraints" => {
+                    let arr = item
+                        .as_array()
+                        .ok_or_else(|| field_err(field, "an array of strings"))?;
+                    for c in arr {
+                        let c = c
+                            .as_str()
+                            .ok_or_else(|| field_err(field, "an array of strings"))?;
+                        constraints.push(Constraint::new(c)?);
+                    }
+                }
+                "deprecated" => {
+                    deprecated = match item.as_value() {
+                        Some(Value::Boolean(b)) if *b.value() => Some(String::new()),
+                        Some(Value::Boolean(_)) => None,
+                        Some(Value::String(s)) => Some(s.value().clone()),
+                        _ => return Err(field_err(field, "a boolean or a string")),
+                    };
+                }
+                "renamed_from" => {
+                    let err = || field_err(field, "a string or an array of strings");
+                    renamed_from = match item.as_value() {
+                        Some(Value::String(s)) => vec![s.value().clone()],
+                        Some(Value::Array(arr)) => arr
+                            .iter()
+                            .map(|v| v.as_str().map(String::from).ok_or_else(err))
+                            .collect::<ConfigResult<_>>()?,
+                        _ => return Err(err()),
+                    };
+                }
+                "by" => {
+                    let s = item.as_str().ok_or_else(|| field_err(field, "a string"))?;
+                    selector = Some(s.to_string());
+                }
+                "variants" => {
+                    let table = item
+                        .as_inline_table()
+                        .ok_or_else(|| field_err(field, "an inline table"))?;
+                    variants = Some(table);
+                }
+                _ => {
+                    return Err(ConfigErr::Other(format!(
+                        "Unknown field `{}` of schema item `{}`",
+                        field, key
+                    )))
+                }
+            }
+        }
+
+        let ty = ty.ok_or_else(|| field_err("type", "specified"))?;
+        let default = default.ok_or_else(|| field_err("default", "specified"))?;
+        let variants = match (&selector, variants) {
+            (Some(_), Some(variants)) => {
+                let variants = std::iter::once(("default", default)).chain(variants.iter());
+                parse_variants(variants, &ty, &constraints)?
+            }
+            (None, None) => Vec::new(),
+            (Some(_), None) => return Err(field_err("variants", "specified with `by`")),
+            (None, Some(_)) => return Err(field_err("by", "specified with `variants`")),
+        };
+        let mut value = ConfigValue::from_raw_value_type(default, ty)?;
+        value.set_constraints(constraints)?;
+        Ok(Self {
+            table_name: table_name.into(),
+            key: key.into(),
+            value,
+            comments,
+            doc: String::new(),
+            deprecated,
+            renamed_from,
+            selector,
+            variants,
+            variant: None,
+            history: Vec::new(),
+        })
+    }
+}
+
+impl Config {
+    /// Parses a config schema into a config object with the default values.
+    ///
+    /// Unlike [`Config::from_toml`], the schema describes each item with a
+    /// TOML table, which contains the following fields:
+    ///
+    /// - `type`: the type of the item (required).
+    /// - `default`: the default value of the item (required).
+    /// - `description`: the documentation of the item.
+    /// - `constraints`: an array of constraints (e.g., `["range(1..=256)"]`).
+    /// - `deprecated`: `true` or a deprecation message.
+    /// - `renamed_from`: the old names of the item (see
+    ///   [`ConfigItem::renamed_from`]).
+    /// - `by` and `variants`: the selector item and an inline table of the
+    ///   value variants other than `default` (see [`Config::resolve_variants`]).
+    ///
+    /// Items in the global table are top-level tables (e.g., `[smp]`), and
+    /// items in other tables are sub-tables (e.g., `[kernel.task-stack-size]`).
+    /// A table can also have a `description` field. The special tables
+    /// (e.g., `[__rules__]`) are the same as in [`Config::from_toml`].
+    ///
+    /// The value files can then be validated against the schema with
+    /// [`Config::overlay`], which rejects unknown keys and keeps the default
+    /// values for the missing ones. Existing specifications can be converted
+    /// into schemas with [`OutputFormat::Schema`](crate::OutputFormat::Schema).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use axconfig_gen::Config;
+    /// let schema = r#"
+    /// [smp]
+    /// type = "uint"
+    /// default = 1
+    /// description = "Number of CPUs."
+    /// constraints = ["range(1..=256)"]
+    ///
+    /// [kernel]
+    /// description = "Kernel configs"
+    ///
+    /// [kernel.task-stack-size]
+    /// type = "uint"
+    /// default = 0x40000
+    /// "#;
+    /// let mut config = Config::from_schema(schema).unwrap();
+    /// config.overlay(&Config::from_toml("smp = 4").unwrap()).unwrap();
+    /// assert!(config.overlay(&Config::from_toml("nr-cpus = 4").unwrap()).is_err());
+    /// ```
+    pub fn from_schema(schema: &str) -> ConfigResult<Self> {
+        let doc = schema.parse::<DocumentMut>()?;
+        let mut result = Self::new();
+        result.parse_type_aliases(doc.as_table())?;
+        let aliases = result.types.clone();
+        for (key, item) in doc.as_table().iter() {
+            let Item::Table(table) = item else {
+                return Err(ConfigErr::Other(format!(
+                    "Schema item `{}` must be a table",
+                    key
+                )));
+            };
+            if result.parse_special_table(key, table)? {
+                continue;
+            }
+            if table.get("type").is_some_and(Item::is_str) {
+                let item = ConfigItem::from_schema(Self::GLOBAL_TABLE_NAME, key, table, &aliases)?;
+                result.global.insert(key.into(), item);
+                continue;
+            }
+
+            let table_name = key;
+            let comments = match table.get("description") {
+                Some(desc) => description_to_comments(desc.as_str().ok_or_else(|| {
+                    ConfigErr::Other(format!(
+                        "Field `description` of table `{}` must be a string",
+                        table_name
+                    ))
+                })?),
+                None => String::new(),
+            };
+            let configs = result.new_table(table_name, &comments)?;
+            for (key, item) in table.iter() {
+                match item {
+                    Item::Table(t) => {
+                        let item = ConfigItem::from_schema(table_name, key, t, &aliases)?;
+                        configs.insert(key.into(), item);
+                    }
+                    _ if key == "description" => {}
+                    _ => {
+                        return Err(ConfigErr::Other(format!(
+                            "Schema item `{}.{}` must be a table",
+                            table_name, key
+                        )))
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Converts the description into comments, each line is prefixed with `# `.
+pub(super) fn description_to_comments(desc: &str) -> String {
+    desc.lines()
+        .map(|l| {
+            if l.is_empty() {
+                "#\n".into()
+            } else {
+                format!("# {}\n", l)
+            }
+        })
+        .collect()
+}
+
+/// Converts the comments into the description, the leading `#` of each line is
+/// removed, and the blank lines at the beginning and the end are trimmed.
+pub(crate) fn comments_to_description(comments: &str) -> String {
+    let lines = comments
+        .lines()
+        .map(|l| {
+            let l = l.trim();
+            let l = l.strip_prefix('#').unwrap_or(l);
+            l.strip_prefix(' ').unwrap_or(l).trim_end()
+        })
+        .collect::<Vec<_>>();
+    let start = lines
+        .iter()
+        .position(|l| !l.is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|l| !l.is_empty())
+        .map_or(start, |i| i + 1);
+    lines[start..end].join("\n")
+}
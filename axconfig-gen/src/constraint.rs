@@ -0,0 +1,159 @@
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+
+use regex::Regex;
+use toml_edit::Value;
+
+use crate::value::{parse_int, to_toml, value_to_int};
+use crate::{ConfigErr, ConfigResult, ConfigType};
+
+/// A constraint on config values.
+///
+/// Constraints are specified in the type comment after the type, separated by
+/// commas, e.g., `# uint, range(1..=256), align(0x1000)`.
+#[derive(Debug, Clone)]
+pub enum Constraint {
+    /// The integer must be in the range (e.g., `range(1..=256)`, `range(1..)`).
+    Range(Bound<i128>, Bound<i128>),
+    /// The integer must be a multiple of the alignment (e.g., `align(0x1000)`).
+    Align(i128),
+    /// The integer must be a power of two (`pow2`).
+    Pow2,
+    /// The string must match the regular expression (e.g., `regex("^[a-z]+$")`).
+    Regex(Regex),
+}
+
+impl Constraint {
+    /// Parses a constraint string into a [`Constraint`].
+    pub fn new(s: &str) -> ConfigResult<Self> {
+        let s = s.trim();
+        let invalid = || ConfigErr::Other(format!("Invalid constraint `{}`", s));
+        if s == "pow2" {
+            return Ok(Self::Pow2);
+        }
+        let (name, arg) = s
+            .strip_suffix(')')
+            .and_then(|s| s.split_once('('))
+            .ok_or_else(invalid)?;
+        let arg = arg.trim();
+        match name.trim() {
+            "range" => {
+                let (start, end) = arg.split_once("..").ok_or_else(invalid)?;
+                let parse = |s: &str| parse_int(s.trim()).ok_or_else(invalid);
+                let start = match start.trim() {
+                    "" => Bound::Unbounded,
+                    s => Bound::Included(parse(s)?),
+                };
+                let end = if let Some(end) = end.strip_prefix('=') {
+                    Bound::Included(parse(end)?)
+                } else if end.trim().is_empty() {
+                    Bound::Unbounded
+                } else {
+                    Bound::Excluded(parse(end)?)
+                };
+                Ok(Self::Range(start, end))
+            }
+            "align" => match parse_int(arg) {
+                Some(align) if align > 0 => Ok(Self::Align(align)),
+                _ => Err(invalid()),
+            },
+            "regex" => {
+                let pattern = match arg.parse::<Value>() {
+                    Ok(Value::String(s)) => s.into_value(),
+                    _ => return Err(invalid()),
+                };
+                Regex::new(&pattern).map(Self::Regex).map_err(|e| {
+                    ConfigErr::Other(format!("Invalid regular expression `{}`: {}", pattern, e))
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Returns whether the constraint applies to values of the specified type.
+    ///
+    /// Integer constraints apply to `int` and `uint` (including the elements
+    /// of arrays and tuples), and `regex` applies to `str` and `char`.
+    pub fn applies_to(&self, ty: &ConfigType) -> bool {
+        match ty {
+            ConfigType::Int | ConfigType::Uint => !matches!(self, Self::Regex(_)),
+            ConfigType::String | ConfigType::Char => matches!(self, Self::Regex(_)),
+            ConfigType::Tuple(items) => items.iter().any(|t| self.applies_to(t)),
+            ConfigType::Array(ty)
+            | ConfigType::FixedArray(ty, _)
+            | ConfigType::Option(ty)
+            | ConfigType::Alias(_, ty)
+            | ConfigType::Mapped(ty, ..) => self.applies_to(ty),
+            _ => false,
+        }
+    }
+
+    /// Checks whether the value of the specified type satisfies the
+    /// constraint.
+    ///
+    /// For arrays and tuples, every element the constraint applies to is
+    /// checked.
+    pub(crate) fn check(&self, value: &Value, ty: &ConfigType) -> ConfigResult<()> {
+        let ok = match (value, ty) {
+            (_, ConfigType::Alias(_, ty) | ConfigType::Mapped(ty, ..)) => {
+                return self.check(value, ty)
+            }
+            (_, ConfigType::Option(ty)) => match value.as_str() {
+                Some("none") => true,
+                _ => return self.check(value, ty),
+            },
+            (Value::Array(arr), ConfigType::Tuple(types)) => {
+                for (v, t) in arr.iter().zip(types) {
+                    self.check(v, t)?;
+                }
+                return Ok(());
+            }
+            (Value::Array(arr), ConfigType::Array(ty) | ConfigType::FixedArray(ty, _)) => {
+                for v in arr {
+                    self.check(v, ty)?;
+                }
+                return Ok(());
+            }
+            (Value::String(s), ConfigType::String | ConfigType::Char) => match self {
+                Self::Regex(re) => re.is_match(s.value()),
+                _ => true,
+            },
+            (_, ConfigType::Int | ConfigType::Uint) => match (self, value_to_int(value)) {
+                (Self::Range(start, end), Some(v)) => (*start, *end).contains(&v),
+                (Self::Align(align), Some(v)) => v % align == 0,
+                (Self::Pow2, Some(v)) => v > 0 && v & (v - 1) == 0,
+                _ => true,
+            },
+            _ => true,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(ConfigErr::ConstraintViolation {
+                constraint: self.to_string(),
+                value: to_toml(value),
+            })
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Range(start, end) => {
+                write!(f, "range(")?;
+                if let Bound::Included(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                match end {
+                    Bound::Included(end) => write!(f, "..={})", end),
+                    Bound::Excluded(end) => write!(f, "..{})", end),
+                    Bound::Unbounded => write!(f, "..)"),
+                }
+            }
+            Self::Align(align) => write!(f, "align({:#x})", align),
+            Self::Pow2 => write!(f, "pow2"),
+            Self::Regex(re) => write!(f, "regex({})", Value::from(re.as_str())),
+        }
+    }
+}
@@ -0,0 +1,644 @@
+use std::fmt;
+
+use toml_edit::Value;
+
+use crate::value::{flags_value, parse_int};
+use crate::{ConfigErr, ConfigResult, ConfigType, ConfigValue};
+
+/// A value produced by evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ExprValue {
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    List(Vec<ExprValue>),
+}
+
+impl ExprValue {
+    /// Converts a config value into an expression value.
+    ///
+    /// Numeric strings are converted into integers unless the value is typed
+    /// as `str` or `char`.
+    pub fn from_config_value(value: &ConfigValue) -> ConfigResult<Self> {
+        let ty = match value.ty() {
+            Some(ty) => ty.clone(),
+            None => value.inferred_type()?,
+        };
+        Ok(Self::from_raw(value.raw(), Some(&ty)))
+    }
+
+    fn from_raw(value: &Value, ty: Option<&ConfigType>) -> Self {
+        match ty {
+            // `"none"` is a string
+            Some(
+                ConfigType::Option(ty) | ConfigType::Alias(_, ty) | ConfigType::Mapped(ty, ..),
+            ) => return Self::from_raw(value, Some(ty)),
+            Some(ConfigType::Flags(flags)) => {
+                if let Some(v) = flags_value(value, flags) {
+                    return Self::Int(v.into());
+                }
+            }
+            _ => {}
+        }
+        match value {
+            Value::Boolean(b) => Self::Bool(*b.value()),
+            Value::Integer(i) => match ty {
+                Some(ConfigType::F32 | ConfigType::F64) => Self::Float(*i.value() as f64),
+                _ => Self::Int(*i.value() as i128),
+            },
+            Value::Float(f) => Self::Float(*f.value()),
+            Value::String(s) => match parse_int(s.value()) {
+                Some(i) if !matches!(ty, Some(ConfigType::String | ConfigType::Char)) => {
+                    Self::Int(i)
+                }
+                _ => Self::Str(s.value().clone()),
+            },
+            Value::Array(arr) => Self::List(
+                arr.iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        let elem_ty = match ty {
+                            Some(ConfigType::Array(t) | ConfigType::FixedArray(t, _)) => {
+                                Some(t.as_ref())
+                            }
+                            Some(ConfigType::Tuple(ts)) => ts.get(i),
+                            _ => None,
+                        };
+                        Self::from_raw(v, elem_ty)
+                    })
+                    .collect(),
+            ),
+            _ => Self::Str(String::new()),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Bool(_) => "bool",
+            Self::Int(_) => "int",
+            Self::Float(_) => "float",
+            Self::Str(_) => "str",
+            Self::List(_) => "list",
+        }
+    }
+
+    fn as_int(&self) -> ConfigResult<i128> {
+        match self {
+            Self::Int(i) => Ok(*i),
+            _ => Err(eval_err(format!(
+                "expected int, found {}",
+                self.type_name()
+            ))),
+        }
+    }
+
+    /// Returns the value as a float, integers are promoted.
+    fn as_float(&self) -> ConfigResult<f64> {
+        match self {
+            Self::Int(i) => Ok(*i as f64),
+            Self::Float(f) => Ok(*f),
+            _ => Err(eval_err(format!(
+                "expected number, found {}",
+                self.type_name()
+            ))),
+        }
+    }
+
+    fn as_bool(&self) -> ConfigResult<bool> {
+        match self {
+            Self::Bool(b) => Ok(*b),
+            _ => Err(eval_err(format!(
+                "expected bool, found {}",
+                self.type_name()
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ExprValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Int(i) if *i < 0 => write!(f, "{}", i),
+            Self::Int(i) => write!(f, "{:#x}", i),
+            Self::Float(v) => write!(f, "{:?}", v),
+            Self::Str(s) => write!(f, "{:?}", s),
+            Self::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Lit(ExprValue),
+    Ident(String),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+    Index(Box<Node>, Box<Node>),
+    Call(String, Vec<Node>),
+}
+
+/// A parsed expression over config items.
+///
+/// Expressions support integer, float, string and boolean literals, config item
+/// references (`key` for global items, `table.key` otherwise), arithmetic
+/// (`+ - * / %`), bitwise (`& | ^ << >>`), comparison (`== != < <= > >=`) and
+/// logical (`&& || !`) operators, indexing (`a[0]`) and the functions `len`,
+/// `min`, `max` and `no_overlap`. Integers are promoted to floats in
+/// arithmetic and comparisons with floats, while bitwise operators only
+/// accept integers.
+///
+/// Since keys may contain `-`, the subtraction operator must be surrounded by
+/// spaces (`a - b`), otherwise it is parsed as a part of the key (`a-b`).
+#[derive(Debug, Clone)]
+pub(crate) struct Expr {
+    src: String,
+    root: Node,
+}
+
+impl Expr {
+    /// Parses an expression string.
+    pub fn parse(src: &str) -> ConfigResult<Self> {
+        let tokens = tokenize(src)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let root = parser.parse_binary(0)?;
+        if parser.pos != parser.tokens.len() {
+            return Err(ConfigErr::Other(format!(
+                "Invalid expression `{}`: unexpected `{}`",
+                src, parser.tokens[parser.pos]
+            )));
+        }
+        Ok(Self {
+            src: src.trim().into(),
+            root,
+        })
+    }
+
+    /// Returns the names of the config items referenced by the expression.
+    pub fn idents(&self) -> Vec<&str> {
+        fn walk<'a>(node: &'a Node, out: &mut Vec<&'a str>) {
+            match node {
+                Node::Lit(_) => {}
+                Node::Ident(name) => {
+                    if !out.contains(&name.as_str()) {
+                        out.push(name)
+                    }
+                }
+                Node::Unary(_, a) => walk(a, out),
+                Node::Binary(_, a, b) | Node::Index(a, b) => {
+                    walk(a, out);
+                    walk(b, out);
+                }
+                Node::Call(_, args) => args.iter().for_each(|a| walk(a, out)),
+            }
+        }
+        let mut out = Vec::new();
+        walk(&self.root, &mut out);
+        out
+    }
+
+    /// Evaluates the expression, `lookup` is used to resolve config item
+    /// references.
+    pub fn eval(
+        &self,
+        lookup: &dyn Fn(&str) -> ConfigResult<ExprValue>,
+    ) -> ConfigResult<ExprValue> {
+        eval(&self.root, lookup)
+            .map_err(|e| ConfigErr::Other(format!("Failed to evaluate `{}`: {}", self.src, e)))
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.src)
+    }
+}
+
+fn eval_err(msg: String) -> ConfigErr {
+    ConfigErr::Other(msg)
+}
+
+fn eval(node: &Node, lookup: &dyn Fn(&str) -> ConfigResult<ExprValue>) -> ConfigResult<ExprValue> {
+    use ExprValue::*;
+    let overflow = || eval_err("arithmetic overflow".into());
+    let is_float = |a: &ExprValue, b: &ExprValue| {
+        matches!((a, b), (Float(_), Int(_) | Float(_)) | (Int(_), Float(_)))
+    };
+    match node {
+        Node::Lit(v) => Ok(v.clone()),
+        Node::Ident(name) => lookup(name),
+        Node::Unary(op, a) => {
+            let a = eval(a, lookup)?;
+            match *op {
+                "!" => Ok(Bool(!a.as_bool()?)),
+                _ if matches!(a, Float(_)) => Ok(Float(-a.as_float()?)),
+                _ => Ok(Int(a.as_int()?.checked_neg().ok_or_else(overflow)?)),
+            }
+        }
+        Node::Binary(op, a, b) => {
+            // short-circuit evaluation
+            if *op == "&&" || *op == "||" {
+                let a = eval(a, lookup)?.as_bool()?;
+                return if (*op == "&&") != a {
+                    Ok(Bool(a))
+                } else {
+                    Ok(Bool(eval(b, lookup)?.as_bool()?))
+                };
+            }
+            let (a, b) = (eval(a, lookup)?, eval(b, lookup)?);
+            let res = match *op {
+                "==" | "!=" if is_float(&a, &b) => {
+                    Bool((a.as_float()? == b.as_float()?) == (*op == "=="))
+                }
+                "==" => Bool(a == b),
+                "!=" => Bool(a != b),
+                "<" | "<=" | ">" | ">=" => {
+                    let ord = match (&a, &b) {
+                        (Str(x), Str(y)) => x.cmp(y),
+                        _ if is_float(&a, &b) => a
+                            .as_float()?
+                            .partial_cmp(&b.as_float()?)
+                            .ok_or_else(|| eval_err("cannot compare NaN".into()))?,
+                        _ => a.as_int()?.cmp(&b.as_int()?),
+                    };
+                    Bool(match *op {
+                        "<" => ord.is_lt(),
+                        "<=" => ord.is_le(),
+                        ">" => ord.is_gt(),
+                        _ => ord.is_ge(),
+                    })
+                }
+                "+" if matches!((&a, &b), (Str(_), Str(_))) => {
+                    Str(format!("{}{}", as_str(&a), as_str(&b)))
+                }
+                "+" | "-" | "*" | "/" | "%" if is_float(&a, &b) => {
+                    let (x, y) = (a.as_float()?, b.as_float()?);
+                    Float(match *op {
+                        "+" => x + y,
+                        "-" => x - y,
+                        "*" => x * y,
+                        "/" => x / y,
+                        _ => x % y,
+                    })
+                }
+                _ => {
+                    let (x, y) = (a.as_int()?, b.as_int()?);
+                    Int(match *op {
+                        "+" => x.checked_add(y),
+                        "-" => x.checked_sub(y),
+                        "*" => x.checked_mul(y),
+                        "/" => x.checked_div(y),
+                        "%" => x.checked_rem(y),
+                        "&" => Some(x & y),
+                        "|" => Some(x | y),
+                        "^" => Some(x ^ y),
+                        "<<" => u32::try_from(y).ok().and_then(|y| x.checked_shl(y)),
+                        ">>" => u32::try_from(y).ok().and_then(|y| x.checked_shr(y)),
+                        _ => unreachable!(),
+                    }
+                    .ok_or_else(overflow)?)
+                }
+            };
+            Ok(res)
+        }
+        Node::Index(a, i) => {
+            let (a, i) = (eval(a, lookup)?, eval(i, lookup)?.as_int()?);
+            match a {
+                List(items) => usize::try_from(i)
+                    .ok()
+                    .and_then(|i| items.get(i).cloned())
+                    .ok_or_else(|| eval_err(format!("index {} out of bounds", i))),
+                _ => Err(eval_err(format!("cannot index {}", a.type_name()))),
+            }
+        }
+        Node::Call(func, args) => {
+            let args = args
+                .iter()
+                .map(|a| eval(a, lookup))
+                .collect::<ConfigResult<Vec<_>>>()?;
+            call(func, &args)
+        }
+    }
+}
+
+fn as_str(v: &ExprValue) -> &str {
+    match v {
+        ExprValue::Str(s) => s,
+        _ => "",
+    }
+}
+
+fn call(func: &str, args: &[ExprValue]) -> ConfigResult<ExprValue> {
+    use ExprValue::*;
+    let arity = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(eval_err(format!(
+                "function `{}` takes {} argument(s), {} given",
+                func,
+                n,
+                args.len()
+            )))
+        }
+    };
+    match func {
+        "len" => {
+            arity(1)?;
+            match &args[0] {
+                List(items) => Ok(Int(items.len() as i128)),
+                Str(s) => Ok(Int(s.chars().count() as i128)),
+                v => Err(eval_err(format!("`len` of {}", v.type_name()))),
+            }
+        }
+        "min" | "max" if args.iter().any(|a| matches!(a, Float(_))) => {
+            let floats = args
+                .iter()
+                .map(ExprValue::as_float)
+                .collect::<ConfigResult<Vec<_>>>()?;
+            let res = floats
+                .into_iter()
+                .reduce(if func == "min" { f64::min } else { f64::max });
+            Ok(Float(res.unwrap()))
+        }
+        "min" | "max" => {
+            let ints = args
+                .iter()
+                .map(ExprValue::as_int)
+                .collect::<ConfigResult<Vec<_>>>()?;
+            let res = if func == "min" {
+                ints.into_iter().min()
+            } else {
+                ints.into_iter().max()
+            };
+            res.map(Int)
+                .ok_or_else(|| eval_err(format!("`{}` of no arguments", func)))
+        }
+        // Whether the regions in the format `[(base, size), ...]` do not overlap,
+        // and do not wrap around the 64-bit address space.
+        "no_overlap" => {
+            arity(1)?;
+            let List(regions) = &args[0] else {
+                return Err(eval_err("`no_overlap` expects a list of regions".into()));
+            };
+            let mut ranges = Vec::new();
+            for r in regions {
+                match r {
+                    List(r) if r.len() == 2 => {
+                        let (base, size) = (r[0].as_int()?, r[1].as_int()?);
+                        match base.checked_add(size) {
+                            Some(end) if end <= 1 << 64 => ranges.push((base, end)),
+                            _ => return Ok(Bool(false)),
+                        }
+                    }
+                    _ => return Err(eval_err("region must be `(base, size)`".into())),
+                }
+            }
+            ranges.sort();
+            Ok(Bool(ranges.windows(2).all(|w| w[0].1 <= w[1].0)))
+        }
+        _ => Err(eval_err(format!("unknown function `{}`", func))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Float(v) => write!(f, "{:?}", v),
+            Self::Str(s) => write!(f, "{:?}", s),
+            Self::Ident(s) => write!(f, "{}", s),
+            Self::Op(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+const OPS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "<", ">", "+", "-", "*", "/", "%", "&", "|",
+    "^", "!", "(", ")", "[", "]", ",",
+];
+
+fn tokenize(src: &str) -> ConfigResult<Vec<Token>> {
+    let err = |msg: &str| ConfigErr::Other(format!("Invalid expression `{}`: {}", src, msg));
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            let is_digit = |i: usize| chars.get(i).is_some_and(char::is_ascii_digit);
+            let mut fraction = false;
+            while i < chars.len() {
+                let ch = chars[i];
+                if ch.is_ascii_alphanumeric() || ch == '_' {
+                    i += 1;
+                } else if ch == '.' && !fraction && is_digit(i + 1) {
+                    // a float literal, e.g., `2.5`
+                    fraction = true;
+                    i += 1;
+                } else if (ch == '-' || ch == '+')
+                    && matches!(chars[i - 1], 'e' | 'E')
+                    && !matches!(chars.get(start + 1), Some('x' | 'o' | 'b'))
+                    && is_digit(i + 1)
+                {
+                    // the signed exponent of a float literal, e.g., `1.5e-3`
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            let s = chars[start..i].iter().collect::<String>();
+            if let Some(int) = parse_int(&s) {
+                tokens.push(Token::Int(int));
+            } else if s.starts_with("0x") || s.starts_with("0o") || s.starts_with("0b") {
+                return Err(err("invalid number"));
+            } else {
+                let float = s.replace('_', "").parse::<f64>();
+                tokens.push(Token::Float(float.map_err(|_| err("invalid number"))?));
+            }
+        } else if c == '"' || c == '\'' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(err("unterminated string")),
+                    Some(&q) if q == c => break,
+                    Some('\\') if i + 1 < chars.len() => {
+                        s.push(chars[i + 1]);
+                        i += 1;
+                    }
+                    Some(&ch) => s.push(ch),
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push(Token::Str(s));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() {
+                let ch = chars[i];
+                let next_is_word = chars.get(i + 1).is_some_and(|c| c.is_alphanumeric());
+                if ch.is_alphanumeric() || ch == '_' || ((ch == '-' || ch == '.') && next_is_word) {
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            let op = OPS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| err(&format!("unexpected character `{}`", c)))?;
+            i += op.len();
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Binary operators grouped by precedence, from the lowest to the highest.
+const BINARY_OPS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<", "<=", ">", ">="],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> ConfigResult<Token> {
+        let tok = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ConfigErr::Other("Invalid expression: unexpected end".into()))?;
+        self.pos += 1;
+        Ok(tok)
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> ConfigResult<()> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(ConfigErr::Other(format!(
+                "Invalid expression: expected `{}`",
+                op
+            )))
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> ConfigResult<Node> {
+        if level == BINARY_OPS.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if !BINARY_OPS[level].contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> ConfigResult<Node> {
+        for op in ["!", "-"] {
+            if self.eat(op) {
+                return Ok(Node::Unary(op, Box::new(self.parse_unary()?)));
+            }
+        }
+        let mut node = self.parse_primary()?;
+        while self.eat("[") {
+            let index = self.parse_binary(0)?;
+            self.expect("]")?;
+            node = Node::Index(Box::new(node), Box::new(index));
+        }
+        Ok(node)
+    }
+
+    fn parse_primary(&mut self) -> ConfigResult<Node> {
+        match self.next()? {
+            Token::Int(i) => Ok(Node::Lit(ExprValue::Int(i))),
+            Token::Float(f) => Ok(Node::Lit(ExprValue::Float(f))),
+            Token::Str(s) => Ok(Node::Lit(ExprValue::Str(s))),
+            Token::Ident(s) if s == "true" || s == "false" => {
+                Ok(Node::Lit(ExprValue::Bool(s == "true")))
+            }
+            Token::Ident(s) => {
+                if self.eat("(") {
+                    let mut args = Vec::new();
+                    if !self.eat(")") {
+                        loop {
+                            args.push(self.parse_binary(0)?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    Ok(Node::Call(s, args))
+                } else {
+                    Ok(Node::Ident(s))
+                }
+            }
+            Token::Op("(") => {
+                let node = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            tok => Err(ConfigErr::Other(format!(
+                "Invalid expression: unexpected `{}`",
+                tok
+            ))),
+        }
+    }
+}
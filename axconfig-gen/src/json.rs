@@ -0,0 +1,88 @@
+use toml_edit::Value;
+
+/// The fields of a JSON object, in order.
+pub(crate) type JsonObject = Vec<(String, Json)>;
+
+/// A minimal JSON value, used to generate JSON documents (e.g., JSON Schema).
+#[derive(Debug, Clone)]
+pub(crate) enum Json {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(JsonObject),
+}
+
+impl Json {
+    /// Converts a TOML value into a JSON value.
+    pub fn from_toml(value: &Value) -> Self {
+        match value {
+            Value::Boolean(b) => Self::Bool(*b.value()),
+            Value::Integer(i) => Self::Int(*i.value()),
+            Value::String(s) => Self::Str(s.value().clone()),
+            Value::Array(arr) => Self::Array(arr.iter().map(Self::from_toml).collect()),
+            Value::InlineTable(t) => Self::Object(
+                t.iter()
+                    .map(|(k, v)| (k.into(), Self::from_toml(v)))
+                    .collect(),
+            ),
+            Value::Float(f) => Self::Float(*f.value()),
+            Value::Datetime(d) => Self::Str(d.value().to_string()),
+        }
+    }
+
+    /// Returns the pretty-printed JSON string, indented by 2 spaces.
+    ///
+    /// Arrays without arrays or objects inside are printed in one line.
+    pub fn pretty(&self, indent: usize) -> String {
+        let inner = format!("{:1$}", "", indent + 2);
+        match self {
+            Self::Bool(b) => b.to_string(),
+            Self::Int(i) => i.to_string(),
+            Self::Float(f) if f.is_finite() => f.to_string(),
+            Self::Float(f) => quote(&f.to_string()),
+            Self::Str(s) => quote(s),
+            Self::Array(arr) if arr.is_empty() => "[]".into(),
+            Self::Array(arr) => {
+                let elements = arr.iter().map(|e| e.pretty(indent + 2));
+                if arr
+                    .iter()
+                    .any(|e| matches!(e, Self::Array(_) | Self::Object(_)))
+                {
+                    let elements = elements.map(|e| format!("{}{}", inner, e));
+                    let elements = elements.collect::<Vec<_>>();
+                    format!("[\n{}\n{:2$}]", elements.join(",\n"), "", indent)
+                } else {
+                    format!("[{}]", elements.collect::<Vec<_>>().join(", "))
+                }
+            }
+            Self::Object(fields) if fields.is_empty() => "{}".into(),
+            Self::Object(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(k, v)| format!("{}{}: {}", inner, quote(k), v.pretty(indent + 2)))
+                    .collect::<Vec<_>>();
+                format!("{{\n{}\n{:2$}}}", fields.join(",\n"), "", indent)
+            }
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
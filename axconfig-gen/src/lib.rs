@@ -1,7 +1,13 @@
 #![doc = include_str!("../README.md")]
 
 mod config;
+mod constraint;
+mod expr;
+mod json;
 mod output;
+mod report;
+#[cfg(feature = "serde")]
+mod serde_impl;
 mod ty;
 mod value;
 
@@ -10,9 +16,11 @@ mod tests;
 
 use toml_edit::TomlError;
 
-pub use self::config::{Config, ConfigItem};
-pub use self::output::OutputFormat;
-pub use self::ty::ConfigType;
+pub use self::config::{Config, ConfigBuilder, ConfigItem, ConfigSource};
+pub use self::constraint::Constraint;
+pub use self::output::{DumpOptions, IdentCase, OutputFormat};
+pub use self::report::{UpdateReport, UpdateWarning};
+pub use self::ty::{ArrayLen, ConfigType};
 pub use self::value::ConfigValue;
 
 /// The error type on config parsing.
@@ -25,6 +33,22 @@ pub enum ConfigErr {
     InvalidType,
     /// Config value and type mismatch.
     ValueTypeMismatch,
+    /// Config value violates a constraint.
+    ConstraintViolation {
+        /// The violated constraint.
+        constraint: String,
+        /// The value in TOML format.
+        value: String,
+    },
+    /// Error in a file loaded by [`Config::from_file`], or in a file it
+    /// includes.
+    Included {
+        /// The include chain, from the file where the error occurred to the
+        /// file loaded first.
+        chain: Vec<String>,
+        /// The error in the included file.
+        err: Box<ConfigErr>,
+    },
     /// Other error.
     Other(String),
 }
@@ -42,6 +66,18 @@ impl core::fmt::Display for ConfigErr {
             Self::InvalidValue => write!(f, "Invalid config value"),
             Self::InvalidType => write!(f, "Invalid config type"),
             Self::ValueTypeMismatch => write!(f, "Config value and type mismatch"),
+            Self::ConstraintViolation { constraint, value } => write!(
+                f,
+                "Config value `{}` violates constraint `{}`",
+                value, constraint
+            ),
+            Self::Included { chain, err } => {
+                write!(f, "{} (in {:?}", err, chain[0])?;
+                for path in &chain[1..] {
+                    write!(f, ", included from {:?}", path)?;
+                }
+                write!(f, ")")
+            }
             Self::Other(s) => write!(f, "{}", s),
         }
     }
@@ -53,5 +89,7 @@ impl core::fmt::Debug for ConfigErr {
     }
 }
 
+impl std::error::Error for ConfigErr {}
+
 /// A specialized [`Result`] type with [`ConfigErr`] as the error type.
 pub type ConfigResult<T> = Result<T, ConfigErr>;
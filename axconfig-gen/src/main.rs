@@ -1,18 +1,36 @@
-use axconfig_gen::{Config, ConfigValue, OutputFormat};
+use axconfig_gen::{
+    Config, ConfigSource, ConfigType, ConfigValue, DumpOptions, IdentCase, OutputFormat,
+    UpdateReport, UpdateWarning,
+};
 use clap::builder::{PossibleValuesParser, TypedValueParser};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version, about, long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Paths to the config specification files
     #[arg(required = true)]
     spec: Vec<String>,
 
+    /// Treat the specification files as schemas, and reject unknown keys in the old config
+    #[arg(short, long)]
+    schema: bool,
+
     /// Path to the old config file
     #[arg(short = 'c', long)]
     oldconfig: Option<String>,
 
+    /// Paths to the config files overlaid on top of the old config, in order
+    #[arg(long, value_name = "OVERLAY")]
+    overlay: Vec<String>,
+
     /// Path to the output config file
     #[arg(short, long)]
     output: Option<String>,
@@ -21,7 +39,7 @@ struct Args {
     #[arg(
         short, long,
         default_value_t = OutputFormat::Toml,
-        value_parser = PossibleValuesParser::new(["toml", "rust"])
+        value_parser = PossibleValuesParser::new(["toml", "rust", "schema"])
             .map(|s| s.parse::<OutputFormat>().unwrap()),
     )]
     fmt: OutputFormat,
@@ -30,15 +48,101 @@ struct Args {
     #[arg(short, long, value_name = "RD_CONFIG")]
     read: Vec<String>,
 
-    /// Setting a config item with format `table.key=value`
+    /// Setting a config item with format `table.key=value`, or `table.key=none` to clear an optional item
     #[arg(short, long, value_name = "WR_CONFIG")]
     write: Vec<String>,
 
-    /// Verbose mode
+    /// Overriding config items with environment variables, e.g., `AX_CONFIG_KERNEL__TASK_STACK_SIZE`
+    #[arg(
+        short, long,
+        value_name = "PREFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "AX_CONFIG_",
+    )]
+    env: Option<String>,
+
+    /// Listing all config items, and why they are disabled if so
     #[arg(short, long)]
+    list: bool,
+
+    /// Explaining where the value of a config item with format `table.key` came from
+    #[arg(long, value_name = "KEY")]
+    explain: Vec<String>,
+
+    /// Only output the config items that differ from the specification defaults
+    #[arg(long)]
+    savedefconfig: bool,
+
+    /// Treating the old config as a minimal one saved by `--savedefconfig`, whose missing items use the defaults without warnings
+    #[arg(long, requires = "oldconfig")]
+    minimal: bool,
+
+    /// Emitting `#[deprecated]` aliases for renamed and deprecated items in the Rust code
+    #[arg(long)]
+    deprecated_aliases: bool,
+
+    /// The case of the constant names in the Rust code
+    #[arg(
+        long,
+        default_value_t = IdentCase::Upper,
+        value_parser = PossibleValuesParser::new(["upper", "lower", "preserve"])
+            .map(|s| s.parse::<IdentCase>().unwrap()),
+    )]
+    const_case: IdentCase,
+
+    /// The case of the module names in the Rust code
+    #[arg(
+        long,
+        default_value_t = IdentCase::Preserve,
+        value_parser = PossibleValuesParser::new(["upper", "lower", "preserve"])
+            .map(|s| s.parse::<IdentCase>().unwrap()),
+    )]
+    mod_case: IdentCase,
+
+    /// Emitting a constant for each flag of the config items of flags types in the Rust code
+    #[arg(long)]
+    flag_consts: bool,
+
+    /// Requiring every config item to have an explicit type annotation
+    #[arg(long)]
+    strict: bool,
+
+    /// Reporting the config items relying on type inference, and their inferred types
+    #[arg(long)]
+    lint_types: bool,
+
+    /// Treating warnings (e.g., unknown or mismatched items in the old config) as errors
+    #[arg(long)]
+    deny_warnings: bool,
+
+    /// The format of the warnings printed to stderr
+    #[arg(
+        long,
+        default_value = "text",
+        value_parser = PossibleValuesParser::new(["text", "json"]),
+    )]
+    report_format: String,
+
+    /// Verbose mode
+    #[arg(short, long, global = true)]
     verbose: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generating a JSON Schema of the config files from the config specifications
+    Schema {
+        /// Paths to the config specification files
+        #[arg(required = true)]
+        spec: Vec<String>,
+
+        /// Path to the output JSON Schema file
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
 fn parse_config_read_arg(arg: &str) -> Result<(String, String), String> {
     if let Some((table, key)) = arg.split_once('.') {
         Ok((table.into(), key.into()))
@@ -73,6 +177,28 @@ macro_rules! unwrap {
     };
 }
 
+fn write_output(path: Option<&str>, output: &str) {
+    if let Some(path) = path.map(std::path::Path::new) {
+        if let Ok(oldconfig) = std::fs::read_to_string(path) {
+            // If the output is the same as the old config, do nothing
+            if oldconfig == output {
+                return;
+            }
+            // Calculate the path to the backup file
+            let bak_path = if let Some(ext) = path.extension() {
+                path.with_extension(format!("old.{}", ext.to_string_lossy()))
+            } else {
+                path.with_extension("old")
+            };
+            // Backup the old config file
+            unwrap!(std::fs::write(bak_path, oldconfig));
+        }
+        unwrap!(std::fs::write(path, output));
+    } else {
+        println!("{}", output);
+    }
+}
+
 fn main() {
     let args = Args::parse();
 
@@ -84,35 +210,110 @@ fn main() {
         };
     }
 
+    // reads a config file and the files it includes
+    let read_config = |path: &str| {
+        Config::from_file_with(path, |path| {
+            debug!("[DEBUG] Reading config file {:?}", path);
+        })
+    };
+
+    let specs = match &args.command {
+        Some(Command::Schema { spec, .. }) => spec,
+        None => &args.spec,
+    };
     let mut config = Config::new();
-    for spec in &args.spec {
+    for spec in specs {
         debug!("[DEBUG] Loading config specification from {:?}", spec);
-        let spec_toml = unwrap!(std::fs::read_to_string(spec).inspect_err(|_| {
-            eprintln!("Failed to read config specification file {:?}", spec);
-        }));
-        let sub_config = unwrap!(Config::from_toml(&spec_toml));
+        let sub_config = if args.schema {
+            let spec_toml = unwrap!(std::fs::read_to_string(spec).inspect_err(|_| {
+                eprintln!("Failed to read config specification file {:?}", spec);
+            }));
+            let mut sub_config = unwrap!(Config::from_schema(&spec_toml));
+            sub_config.set_source(ConfigSource::new("spec", Some(spec)));
+            sub_config
+        } else {
+            let mut sub_config = unwrap!(read_config(spec));
+            sub_config.set_layer("spec");
+            sub_config
+        };
         unwrap!(config.merge(&sub_config));
     }
 
-    if let Some(oldconfig_path) = &args.oldconfig {
-        debug!("[DEBUG] Loading old config from {:?}", oldconfig_path);
-        let oldconfig_toml = unwrap!(std::fs::read_to_string(oldconfig_path).inspect_err(|_| {
-            eprintln!("Failed to read old config file {:?}", oldconfig_path);
-        }));
-        let oldconfig = unwrap!(Config::from_toml(&oldconfig_toml));
+    if let Some(Command::Schema { output, .. }) = &args.command {
+        unwrap!(config.resolve_variants());
+        let schema = unwrap!(config.dump(OutputFormat::JsonSchema));
+        write_output(output.as_deref(), &schema);
+        return;
+    }
+
+    if args.strict {
+        // checked on the specifications, also in reading mode
+        unwrap!(config.check_strict());
+    }
 
-        let (untouched, extra) = unwrap!(config.update(&oldconfig));
-        for item in &untouched {
+    if args.lint_types {
+        for (item, ty) in unwrap!(config.inferred_items()) {
             eprintln!(
-                "[WARN] config item `{}` not set in the old config, using default value",
+                "[LINT] config item `{}` = {} has no type annotation, inferred as `{}`",
                 item.item_name(),
+                item.value().to_toml_value(),
+                ty
             );
         }
-        for item in &extra {
-            eprintln!(
-                "[WARN] config item `{}` not found in the specification, ignoring",
-                item.item_name(),
-            );
+    }
+
+    let defconfig = args.savedefconfig.then(|| config.clone());
+    let mut warnings = UpdateReport::default();
+
+    if let Some(oldconfig_path) = &args.oldconfig {
+        debug!("[DEBUG] Loading old config from {:?}", oldconfig_path);
+        let mut oldconfig = unwrap!(read_config(oldconfig_path));
+        oldconfig.set_layer("oldconfig");
+
+        let report = if args.schema {
+            // Missing keys fall back to the defaults silently, unknown keys are errors.
+            unwrap!(config.overlay(&oldconfig))
+        } else {
+            unwrap!(config.update(&oldconfig))
+        };
+        // the items left out of a minimal config are expected to be missing
+        let untouched = |w: &&UpdateWarning| matches!(w, UpdateWarning::Untouched { .. });
+        let report = report.warnings().iter();
+        warnings.extend(report.filter(|w| !(args.minimal && untouched(w))).cloned());
+    }
+
+    for (i, overlay_path) in args.overlay.iter().enumerate() {
+        debug!("[DEBUG] Loading overlay config from {:?}", overlay_path);
+        let mut overlay = unwrap!(read_config(overlay_path));
+        // numbered to tell the layers apart in `--explain`
+        let layer = format!("overlay {}", i + 1);
+        overlay.set_layer(&layer);
+        let report = unwrap!(config.overlay(&overlay));
+        warnings.extend(report.warnings().iter().cloned());
+    }
+
+    if args.report_format == "json" {
+        if !warnings.is_empty() {
+            eprintln!("{}", warnings.to_json());
+        }
+    } else {
+        for warning in warnings.warnings() {
+            eprintln!("[WARN] {}", warning);
+        }
+    }
+    if args.deny_warnings && !warnings.is_empty() {
+        eprintln!("{} warning(s) treated as errors", warnings.warnings().len());
+        std::process::exit(1);
+    }
+
+    if let Some(prefix) = &args.env {
+        debug!(
+            "[DEBUG] Applying environment variables with prefix {:?}",
+            prefix
+        );
+        let overridden = unwrap!(config.apply_env(prefix, |name| std::env::var(name).ok()));
+        for name in overridden {
+            debug!("[DEBUG] Config item `{}` overridden by environment", name);
         }
     }
 
@@ -126,13 +327,29 @@ fn main() {
                 table, key, value
             );
         }
-        let new_value = unwrap!(ConfigValue::new(&value));
         let item = unwrap!(config
             .config_at_mut(&table, &key)
             .ok_or_else(|| format!("Config item `{}` not found", arg)));
-        unwrap!(item.value_mut().update(new_value));
+        // a bare `none` clears the value of optional items, while a quoted
+        // `"none"` is parsed as a string
+        let new_value = if value == "none" {
+            let ty = item.value().ty().map(ConfigType::resolved);
+            unwrap!(matches!(ty, Some(ConfigType::Option(_)))
+                .then(ConfigValue::none)
+                .ok_or_else(|| format!(
+                    "Config item `{}` is not optional, use `\"none\"` for the string",
+                    item.item_name()
+                )))
+        } else {
+            unwrap!(ConfigValue::new(&value))
+        };
+        unwrap!(item.update_from(new_value, ConfigSource::new("command line", None)));
     }
 
+    // after all the layers, which may change the selectors
+    unwrap!(config.resolve_variants());
+    unwrap!(config.check_rules());
+
     for arg in &args.read {
         let (table, key) = unwrap!(parse_config_read_arg(arg));
         if table == Config::GLOBAL_TABLE_NAME {
@@ -146,29 +363,43 @@ fn main() {
         println!("{}", item.value().to_toml_value());
     }
 
-    if !args.read.is_empty() {
+    if args.list {
+        for item in config.iter() {
+            let value = item.value().to_toml_value();
+            match unwrap!(config.disabled_by(item)) {
+                Some(cond) => println!("{} = {} # disabled: {}", item.item_name(), value, cond),
+                None => println!("{} = {}", item.item_name(), value),
+            }
+        }
+    }
+
+    for arg in &args.explain {
+        let (table, key) = unwrap!(parse_config_read_arg(arg));
+        let item = unwrap!(config
+            .config_at(&table, &key)
+            .ok_or_else(|| format!("Config item `{}` not found", arg)));
+        println!("{} = {}", item.item_name(), item.value().to_toml_value());
+        for (source, value) in item.history() {
+            println!("    {}: {}", source, value.to_toml_value());
+        }
+    }
+
+    if args.list || !args.read.is_empty() || !args.explain.is_empty() {
         debug!("[DEBUG] In reading mode, no output");
         return;
     }
 
-    let output = unwrap!(config.dump(args.fmt));
-    if let Some(path) = args.output.as_ref().map(std::path::Path::new) {
-        if let Ok(oldconfig) = std::fs::read_to_string(path) {
-            // If the output is the same as the old config, do nothing
-            if oldconfig == output {
-                return;
-            }
-            // Calculate the path to the backup file
-            let bak_path = if let Some(ext) = path.extension() {
-                path.with_extension(format!("old.{}", ext.to_string_lossy()))
-            } else {
-                path.with_extension("old")
-            };
-            // Backup the old config file
-            unwrap!(std::fs::write(bak_path, oldconfig));
-        }
-        unwrap!(std::fs::write(path, output));
-    } else {
-        println!("{}", output);
+    if let Some(defconfig) = &defconfig {
+        debug!("[DEBUG] Removing config items with default values");
+        config = unwrap!(config.diff(defconfig));
     }
+
+    let opts = DumpOptions::default()
+        .deprecated_aliases(args.deprecated_aliases)
+        .strict(args.strict)
+        .const_case(args.const_case)
+        .mod_case(args.mod_case)
+        .flag_consts(args.flag_consts);
+    let output = unwrap!(config.dump_with_options(args.fmt, &opts));
+    write_output(args.output.as_deref(), &output);
 }
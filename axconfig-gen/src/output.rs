@@ -1,4 +1,18 @@
-use crate::{ConfigErr, ConfigItem, ConfigResult, ConfigType};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use toml_edit::{Key, Value};
+
+use crate::config::{comments_to_description, doc_comment};
+use crate::json::{Json, JsonObject};
+use crate::ty::alias_rust_name;
+use crate::{
+    ArrayLen, Config, ConfigErr, ConfigItem, ConfigResult, ConfigType, ConfigValue, Constraint,
+};
+
+const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+const INT_PATTERN: &str = "^-?(0[xX][0-9a-fA-F_]+|0[bB][01_]+|0[oO][0-7_]+|[0-9_]+)$";
+const UINT_PATTERN: &str = "^(0[xX][0-9a-fA-F_]+|0[bB][01_]+|0[oO][0-7_]+|[0-9_]+)$";
 
 /// The format of the generated file.
 #[derive(Debug, Clone)]
@@ -7,6 +21,11 @@ pub enum OutputFormat {
     Toml,
     /// Output is Rust code.
     Rust,
+    /// Output is a config schema in TOML format (see [`Config::from_schema`](crate::Config::from_schema)).
+    Schema,
+    /// Output is a JSON Schema of the config file, for validation and
+    /// completion in editors.
+    JsonSchema,
 }
 
 impl std::fmt::Display for OutputFormat {
@@ -14,6 +33,8 @@ impl std::fmt::Display for OutputFormat {
         let s = match self {
             Self::Toml => "toml",
             Self::Rust => "rust",
+            Self::Schema => "schema",
+            Self::JsonSchema => "json-schema",
         };
         s.fmt(f)
     }
@@ -26,29 +47,203 @@ impl std::str::FromStr for OutputFormat {
         match s {
             "toml" => Ok(Self::Toml),
             "rust" => Ok(Self::Rust),
+            "schema" => Ok(Self::Schema),
+            "json-schema" => Ok(Self::JsonSchema),
+            _ => Err(s.into()),
+        }
+    }
+}
+
+/// The case of the Rust identifiers generated from the keys.
+///
+/// Regardless of the case, characters that are not allowed in identifiers
+/// (e.g., `-`, `.` or non-ASCII characters) are replaced by `_`, a `_` is
+/// prepended to a leading digit, and keywords are escaped as raw identifiers
+/// (e.g., `r#type`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentCase {
+    /// `UPPER_SNAKE_CASE`.
+    Upper,
+    /// `lower_snake_case`.
+    Lower,
+    /// Keep the case of the keys.
+    Preserve,
+}
+
+impl std::fmt::Display for IdentCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Upper => "upper",
+            Self::Lower => "lower",
+            Self::Preserve => "preserve",
+        };
+        s.fmt(f)
+    }
+}
+
+impl std::str::FromStr for IdentCase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upper" => Ok(Self::Upper),
+            "lower" => Ok(Self::Lower),
+            "preserve" => Ok(Self::Preserve),
             _ => Err(s.into()),
         }
     }
 }
 
+/// The options of dumping the config.
+///
+/// New options may be added in the future, so use the setters on
+/// [`DumpOptions::default`] to construct it.
+///
+/// # Example
+///
+/// ```
+/// # use axconfig_gen::{DumpOptions, IdentCase};
+/// let opts = DumpOptions::default()
+///     .strict(true)
+///     .const_case(IdentCase::Lower);
+/// assert!(opts.strict);
+/// ```
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DumpOptions {
+    /// Whether to emit `#[deprecated]` attributes for deprecated items, and
+    /// `#[deprecated]` alias constants for the old names of renamed items in
+    /// the same table, in the Rust code.
+    pub deprecated_aliases: bool,
+    /// Whether every item must carry an explicit type annotation. If set,
+    /// dumping fails on items whose types are inferred, instead of relying on
+    /// the inference or skipping the items of unknown types.
+    pub strict: bool,
+    /// The case of the constant names in the Rust code, [`IdentCase::Upper`]
+    /// by default.
+    pub const_case: IdentCase,
+    /// The case of the module names (i.e., table names) in the Rust code,
+    /// [`IdentCase::Preserve`] by default.
+    pub mod_case: IdentCase,
+    /// Whether to emit a constant for each flag of the items of flags types,
+    /// named after the item and the flag (e.g., `MMIO_ATTRS_READ`), in the
+    /// Rust code.
+    pub flag_consts: bool,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            deprecated_aliases: false,
+            strict: false,
+            const_case: IdentCase::Upper,
+            mod_case: IdentCase::Preserve,
+            flag_consts: false,
+        }
+    }
+}
+
+impl DumpOptions {
+    /// Sets [`deprecated_aliases`](Self::deprecated_aliases).
+    pub fn deprecated_aliases(mut self, deprecated_aliases: bool) -> Self {
+        self.deprecated_aliases = deprecated_aliases;
+        self
+    }
+
+    /// Sets [`strict`](Self::strict).
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets [`const_case`](Self::const_case).
+    pub fn const_case(mut self, const_case: IdentCase) -> Self {
+        self.const_case = const_case;
+        self
+    }
+
+    /// Sets [`mod_case`](Self::mod_case).
+    pub fn mod_case(mut self, mod_case: IdentCase) -> Self {
+        self.mod_case = mod_case;
+        self
+    }
+
+    /// Sets [`flag_consts`](Self::flag_consts).
+    pub fn flag_consts(mut self, flag_consts: bool) -> Self {
+        self.flag_consts = flag_consts;
+        self
+    }
+}
+
+/// Returns the description of the item, i.e., the comments followed by the
+/// inline documentation.
+fn item_description(item: &ConfigItem) -> String {
+    let desc = comments_to_description(item.comments());
+    match (desc.is_empty(), item.doc().is_empty()) {
+        (_, true) => desc,
+        (true, false) => item.doc().into(),
+        (false, false) => format!("{}\n{}", desc, item.doc()),
+    }
+}
+
 /// The output writer.
 pub struct Output {
     fmt: OutputFormat,
+    opts: DumpOptions,
     indent: usize,
     result: String,
+    table: Option<String>,
+    json: JsonObject,
+    json_table: Option<(String, String, JsonObject)>,
+    /// The Rust identifiers of the tables and the items in the current table,
+    /// mapped to their source names, to detect collisions.
+    mod_names: BTreeMap<String, String>,
+    const_names: BTreeMap<String, String>,
 }
 
 impl Output {
-    pub fn new(fmt: OutputFormat) -> Self {
+    pub fn new(fmt: OutputFormat, opts: DumpOptions) -> Self {
         Self {
             fmt,
+            opts,
             indent: 0,
             result: String::new(),
+            table: None,
+            json: Vec::new(),
+            json_table: None,
+            mod_names: BTreeMap::new(),
+            const_names: BTreeMap::new(),
         }
     }
 
-    pub fn result(&self) -> &str {
-        &self.result
+    pub fn finish(mut self) -> String {
+        if let OutputFormat::JsonSchema = self.fmt {
+            // the special tables and keys written or read by the tool
+            let str = |s: &str| Json::Str(s.into());
+            let string = || Json::Object(vec![("type".into(), str("string"))]);
+            let mut props = self.json;
+            for name in [
+                Config::RULES_TABLE_NAME,
+                Config::DEPENDS_ON_TABLE_NAME,
+                Config::TYPES_TABLE_NAME,
+            ] {
+                let table = vec![
+                    ("type".into(), str("object")),
+                    ("additionalProperties".into(), string()),
+                ];
+                props.push((name.into(), Json::Object(table)));
+            }
+            let include = vec![("type".into(), str("array")), ("items".into(), string())];
+            props.push((Config::INCLUDE_KEY.into(), Json::Object(include)));
+            let root = Json::Object(vec![
+                ("$schema".into(), Json::Str(JSON_SCHEMA_DIALECT.into())),
+                ("type".into(), Json::Str("object".into())),
+                ("properties".into(), Json::Object(props)),
+                ("additionalProperties".into(), Json::Bool(false)),
+            ]);
+            self.result = root.pretty(0) + "\n";
+        }
+        self.result
     }
 
     pub fn println_fmt(&mut self, fmt: std::fmt::Arguments) {
@@ -68,27 +263,203 @@ impl Output {
         }
     }
 
-    pub fn table_begin(&mut self, name: &str, comments: &str) {
-        if !self.result.is_empty() {
+    /// Records the Rust identifier generated from `source`, returns an error
+    /// if it collides with the one of another table or item.
+    fn declare(
+        names: &mut BTreeMap<String, String>,
+        ident: &str,
+        kind: &str,
+        source: String,
+    ) -> ConfigResult<()> {
+        match names.get(ident) {
+            Some(other) => Err(ConfigErr::Other(format!(
+                "Rust identifier `{}` of {} `{}` collides with {} `{}`",
+                ident, kind, source, kind, other
+            ))),
+            None => {
+                names.insert(ident.into(), source);
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks that the Rust identifiers of the item (and its aliases) do not
+    /// collide with those of other items in the same table.
+    pub fn declare_item(&mut self, item: &ConfigItem) -> ConfigResult<()> {
+        if !matches!(self.fmt, OutputFormat::Rust) {
+            return Ok(());
+        }
+        let case = self.opts.const_case;
+        let names = &mut self.const_names;
+        Self::declare(
+            names,
+            &rust_ident(item.key(), case),
+            "item",
+            item.item_name(),
+        )?;
+        if self.opts.deprecated_aliases {
+            for old in item.renamed_from().iter().filter(|n| !n.contains('.')) {
+                let old_name = match &self.table {
+                    Some(table) => format!("{}.{}", table, old),
+                    None => old.clone(),
+                };
+                Self::declare(names, &rust_ident(old, case), "item", old_name)?;
+            }
+        }
+        for (ident, _) in self.flag_consts(item) {
+            Self::declare(&mut self.const_names, &ident, "item", item.item_name())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the Rust identifiers and values of the flag constants of the
+    /// item, if enabled.
+    fn flag_consts(&self, item: &ConfigItem) -> Vec<(String, u64)> {
+        match item.value().ty().map(ConfigType::resolved) {
+            Some(ConfigType::Flags(flags)) if self.opts.flag_consts => flags
+                .iter()
+                .map(|(name, value)| {
+                    let name = format!("{}_{}", item.key(), name);
+                    (rust_ident(&name, self.opts.const_case), *value)
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the Rust type in the current module, where the lengths of
+    /// fixed-size arrays referring to other items are converted into the paths
+    /// of the constants, and the type aliases into the paths of the types.
+    fn rust_type(&self, ty: &ConfigType) -> String {
+        let alias = |name: &str| match self.table {
+            Some(_) => format!("super::{}", alias_rust_name(name)),
+            None => alias_rust_name(name),
+        };
+        let len = |len: &ArrayLen| match len {
+            ArrayLen::Fixed(n) => n.to_string(),
+            ArrayLen::Item(name) => {
+                let (table, key) = match name.split_once('.') {
+                    Some((table, key)) => (Some(table), key),
+                    None => (None, name.as_str()),
+                };
+                let key = rust_ident(key, self.opts.const_case);
+                let module = table.map(|t| rust_ident(t, self.opts.mod_case));
+                match (self.table.as_deref(), table) {
+                    (cur, target) if cur == target => key,
+                    (None, _) => format!("{}::{}", module.unwrap(), key),
+                    (Some(_), None) => format!("super::{}", key),
+                    (Some(_), Some(_)) => format!("super::{}::{}", module.unwrap(), key),
+                }
+            }
+        };
+        ty.to_rust_type_with(&len, &alias)
+    }
+
+    /// Writes the type aliases, each with its name, type and comments.
+    pub fn write_type_aliases<'a>(
+        &mut self,
+        aliases: impl Iterator<Item = (&'a str, &'a ConfigType, &'a str)>,
+    ) -> ConfigResult<()> {
+        let mut aliases = aliases.peekable();
+        if aliases.peek().is_none() {
+            return Ok(());
+        }
+        match self.fmt {
+            OutputFormat::Toml | OutputFormat::Schema => {
+                if !self.result.is_empty() {
+                    self.println("");
+                }
+                self.println(&format!("[{}]", Config::TYPES_TABLE_NAME));
+                for (name, ty, comments) in aliases {
+                    self.print_lines(comments, |l| l.trim().into());
+                    let ty = Value::from(ty.to_string());
+                    self.println(&format!("{} = {}", toml_key(name), ty));
+                }
+            }
+            OutputFormat::Rust => {
+                for (name, ty, comments) in aliases {
+                    let ident = alias_rust_name(name);
+                    Self::declare(&mut self.mod_names, &ident, "type alias", name.into())?;
+                    self.print_lines(comments, |l| l.trim().replacen("#", "///", 1));
+                    // references in type aliases need explicit lifetimes
+                    let rust_ty = self.rust_type(ty).replace('&', "&'static ");
+                    self.println(&format!("pub type {} = {};", ident, rust_ty));
+                }
+                self.println("");
+            }
+            OutputFormat::JsonSchema => {}
+        }
+        Ok(())
+    }
+
+    pub fn table_begin(&mut self, name: &str, comments: &str) -> ConfigResult<()> {
+        if !self.result.is_empty() && !self.result.ends_with("\n\n") {
             self.println("");
         }
+        self.table = Some(name.into());
         match self.fmt {
             OutputFormat::Toml => {
                 self.print_lines(comments, |l| l.trim().into());
-                self.println(&format!("[{}]", name));
+                self.println(&format!("[{}]", toml_key(name)));
             }
             OutputFormat::Rust => {
+                let ident = rust_ident(name, self.opts.mod_case);
+                Self::declare(&mut self.mod_names, &ident, "table", name.into())?;
+                self.const_names.clear();
                 self.print_lines(comments, |l| l.trim().replacen("#", "///", 1));
-                self.println_fmt(format_args!("pub mod {} {{", mod_name(name)));
+                self.println_fmt(format_args!("pub mod {} {{", ident));
                 self.indent += 4;
             }
+            OutputFormat::Schema => {
+                let desc = comments_to_description(comments);
+                if !desc.is_empty() {
+                    self.println(&format!("[{}]", toml_key(name)));
+                    self.println(&format!("description = {}", Value::from(desc)));
+                }
+            }
+            OutputFormat::JsonSchema => {
+                let desc = comments_to_description(comments);
+                self.json_table = Some((name.into(), desc, Vec::new()));
+            }
         }
+        Ok(())
     }
 
     pub fn table_end(&mut self) {
-        if let OutputFormat::Rust = self.fmt {
-            self.indent -= 4;
-            self.println("}");
+        self.table = None;
+        match self.fmt {
+            OutputFormat::Rust => {
+                self.indent -= 4;
+                self.println("}");
+            }
+            OutputFormat::JsonSchema => {
+                if let Some((name, desc, props)) = self.json_table.take() {
+                    let mut schema = Vec::new();
+                    if !desc.is_empty() {
+                        schema.push(("description".into(), Json::Str(desc)));
+                    }
+                    schema.push(("type".into(), Json::Str("object".into())));
+                    schema.push(("properties".into(), Json::Object(props)));
+                    schema.push(("additionalProperties".into(), Json::Bool(false)));
+                    self.json.push((name, Json::Object(schema)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes a special table of strings (e.g., `[__rules__]`).
+    pub fn write_exprs<'a>(&mut self, name: &str, exprs: impl Iterator<Item = (&'a str, String)>) {
+        let mut exprs = exprs.peekable();
+        if exprs.peek().is_none() {
+            return;
+        }
+        if !self.result.is_empty() {
+            self.println("");
+        }
+        self.println(&format!("[{}]", name));
+        for (key, expr) in exprs {
+            self.println(&format!("{} = {}", toml_key(key), Value::from(expr)));
         }
     }
 
@@ -98,10 +469,21 @@ impl Output {
                 self.print_lines(item.comments(), |l| l.trim().into());
                 self.println_fmt(format_args!(
                     "{} = {}{}",
-                    item.key(),
-                    item.value().to_toml_value(),
+                    toml_key(item.key()),
+                    toml_item_value(item),
                     if let Some(ty) = item.value().ty() {
-                        format!(" # {}", ty)
+                        let constraints = item.value().constraints().iter();
+                        let annotation = std::iter::once(ty.to_string())
+                            .chain(constraints.map(|c| c.to_string()))
+                            .chain(metadata_annotations(item))
+                            .collect::<Vec<_>>();
+                        if item.doc().is_empty() {
+                            format!(" # {}", annotation.join(", "))
+                        } else {
+                            format!(" # {}; {}", annotation.join(", "), item.doc())
+                        }
+                    } else if !item.doc().is_empty() {
+                        format!(" {}", doc_comment(item.doc()))
                     } else {
                         "".into()
                     },
@@ -109,7 +491,10 @@ impl Output {
             }
             OutputFormat::Rust => {
                 self.print_lines(item.comments(), |l| l.trim().replacen("#", "///", 1));
-                let key = const_name(item.key());
+                if !item.doc().is_empty() {
+                    self.println(&format!("/// {}", item.doc()));
+                }
+                let key = rust_ident(item.key(), self.opts.const_case);
                 let val = item.value();
                 let ty = if let Some(ty) = val.ty() {
                     ty.clone()
@@ -123,22 +508,331 @@ impl Output {
                         item.key()
                     )));
                 }
+                let deprecated = item.deprecated().filter(|_| self.opts.deprecated_aliases);
+                match deprecated {
+                    Some("") => self.println("#[deprecated]"),
+                    Some(msg) => self.println(&format!("#[deprecated(note = {:?})]", msg)),
+                    None => {}
+                }
                 self.println_fmt(format_args!(
                     "pub const {}: {} = {};",
                     key,
-                    ty.to_rust_type(),
+                    self.rust_type(&ty),
                     val.to_rust_value(&ty, self.indent)?,
                 ));
+                for (ident, value) in self.flag_consts(item) {
+                    self.println(&format!("pub const {}: usize = {:#x};", ident, value));
+                }
+                if self.opts.deprecated_aliases {
+                    // old names in other tables are not emitted
+                    for old in item.renamed_from().iter().filter(|n| !n.contains('.')) {
+                        let note = format!("renamed to `{}`", key);
+                        self.println(&format!("#[deprecated(note = {:?})]", note));
+                        if deprecated.is_some() {
+                            self.println("#[allow(deprecated)]");
+                        }
+                        self.println_fmt(format_args!(
+                            "pub const {}: {} = {};",
+                            rust_ident(old, self.opts.const_case),
+                            self.rust_type(&ty),
+                            key,
+                        ));
+                    }
+                }
+            }
+            OutputFormat::Schema => {
+                let val = item.value();
+                let ty = if let Some(ty) = val.ty() {
+                    ty.clone()
+                } else {
+                    val.inferred_type()?
+                };
+                if matches!(ty, ConfigType::Unknown) {
+                    return Err(ConfigErr::Other(format!(
+                        "Unknown type for key `{}`",
+                        item.key()
+                    )));
+                }
+
+                if !self.result.is_empty() {
+                    self.println("");
+                }
+                let header = match &self.table {
+                    Some(table) => format!("[{}.{}]", toml_key(table), toml_key(item.key())),
+                    None => format!("[{}]", toml_key(item.key())),
+                };
+                self.println(&header);
+                let desc = item_description(item);
+                if !desc.is_empty() {
+                    self.println(&format!("description = {}", Value::from(desc)));
+                }
+                self.println(&format!("type = {}", Value::from(ty.to_string())));
+                // the default variant, the value may have been resolved
+                let default = item.variants().first().map_or(val, |(_, v)| v);
+                self.println(&format!("default = {}", default.to_toml_value()));
+                if !val.constraints().is_empty() {
+                    let constraints = val
+                        .constraints()
+                        .iter()
+                        .map(|c| Value::from(c.to_string()).to_string())
+                        .collect::<Vec<_>>();
+                    self.println(&format!("constraints = [{}]", constraints.join(", ")));
+                }
+                match item.deprecated() {
+                    Some("") => self.println("deprecated = true"),
+                    Some(msg) => self.println(&format!("deprecated = {}", Value::from(msg))),
+                    None => {}
+                }
+                if !item.renamed_from().is_empty() {
+                    let names = string_list(item.renamed_from());
+                    self.println(&format!("renamed_from = [{}]", names));
+                }
+                if let Some(selector) = item.selector() {
+                    self.println(&format!("by = {}", Value::from(selector)));
+                    let variants = variants_toml(&item.variants()[1..]);
+                    self.println(&format!("variants = {}", variants));
+                }
+            }
+            OutputFormat::JsonSchema => {
+                let val = item.value();
+                let ty = if let Some(ty) = val.ty() {
+                    ty.clone()
+                } else {
+                    val.inferred_type()?
+                };
+                let mut schema = Vec::new();
+                let desc = item_description(item);
+                if !desc.is_empty() {
+                    schema.push(("description".into(), Json::Str(desc)));
+                }
+                schema.extend(type_json_schema(&ty, val.constraints()));
+                schema.push(("default".into(), Json::from_toml(val.raw())));
+                if item.deprecated().is_some() {
+                    schema.push(("deprecated".into(), Json::Bool(true)));
+                }
+                let entry = (item.key().into(), Json::Object(schema));
+                match &mut self.json_table {
+                    Some((_, _, props)) => props.push(entry),
+                    None => self.json.push(entry),
+                }
             }
         }
         Ok(())
     }
 }
 
-fn mod_name(name: &str) -> String {
-    name.replace("-", "_")
+/// Returns the JSON Schema keywords of values of the specified type.
+///
+/// Integers can also be written as strings (e.g., `"0xffff_ff80_0000_0000"`),
+/// which are validated by a pattern. The constraints are mapped to the
+/// keywords of the elements they apply to, except `pow2`, which has no
+/// equivalent in JSON Schema.
+fn type_json_schema(ty: &ConfigType, constraints: &[Constraint]) -> JsonObject {
+    let str = |s: &str| Json::Str(s.into());
+    let mut schema = Vec::new();
+    match ty {
+        ConfigType::Bool => schema.push(("type".into(), str("boolean"))),
+        ConfigType::Int => {
+            schema.push((
+                "type".into(),
+                Json::Array(vec![str("integer"), str("string")]),
+            ));
+            schema.push(("pattern".into(), str(INT_PATTERN)));
+        }
+        ConfigType::Uint => {
+            schema.push((
+                "type".into(),
+                Json::Array(vec![str("integer"), str("string")]),
+            ));
+            schema.push(("pattern".into(), str(UINT_PATTERN)));
+            schema.push(("minimum".into(), Json::Int(0)));
+        }
+        ConfigType::String => schema.push(("type".into(), str("string"))),
+        ConfigType::F32 | ConfigType::F64 => schema.push(("type".into(), str("number"))),
+        ConfigType::Char => {
+            schema.push(("type".into(), str("string")));
+            schema.push(("minLength".into(), Json::Int(1)));
+            schema.push(("maxLength".into(), Json::Int(1)));
+        }
+        ConfigType::Tuple(items) => {
+            let items = items
+                .iter()
+                .map(|ty| Json::Object(type_json_schema(ty, constraints)))
+                .collect::<Vec<_>>();
+            let len = Json::Int(items.len() as i64);
+            schema.push(("type".into(), str("array")));
+            schema.push(("prefixItems".into(), Json::Array(items)));
+            schema.push(("minItems".into(), len.clone()));
+            schema.push(("maxItems".into(), len));
+        }
+        ConfigType::Array(elem) | ConfigType::FixedArray(elem, _) => {
+            schema.push(("type".into(), str("array")));
+            let items = type_json_schema(elem, constraints);
+            schema.push(("items".into(), Json::Object(items)));
+            if let ConfigType::FixedArray(_, ArrayLen::Fixed(n)) = ty {
+                schema.push(("minItems".into(), Json::Int(*n as i64)));
+                schema.push(("maxItems".into(), Json::Int(*n as i64)));
+            }
+        }
+        ConfigType::Flags(flags) => {
+            let names = flags.iter().map(|(name, _)| str(name)).collect();
+            let names = vec![
+                ("type".into(), str("array")),
+                (
+                    "items".into(),
+                    Json::Object(vec![("enum".into(), Json::Array(names))]),
+                ),
+                ("uniqueItems".into(), Json::Bool(true)),
+            ];
+            let int = vec![
+                (
+                    "type".into(),
+                    Json::Array(vec![str("integer"), str("string")]),
+                ),
+                ("pattern".into(), str(UINT_PATTERN)),
+                ("minimum".into(), Json::Int(0)),
+            ];
+            let any_of = vec![Json::Object(names), Json::Object(int)];
+            schema.push(("anyOf".into(), Json::Array(any_of)));
+        }
+        ConfigType::Alias(_, ty) | ConfigType::Mapped(ty, ..) => {
+            return type_json_schema(ty, constraints)
+        }
+        ConfigType::Option(ty) => {
+            let none = vec![("const".into(), str("none"))];
+            let any_of = vec![
+                Json::Object(type_json_schema(ty, constraints)),
+                Json::Object(none),
+            ];
+            schema.push(("anyOf".into(), Json::Array(any_of)));
+            return schema;
+        }
+        ConfigType::Unknown => {}
+    }
+    if !matches!(
+        ty,
+        ConfigType::Tuple(_) | ConfigType::Array(_) | ConfigType::FixedArray(..)
+    ) {
+        for c in constraints.iter().filter(|c| c.applies_to(ty)) {
+            // later constraints override the keywords of the former ones
+            for (key, value) in constraint_json_schema(c) {
+                schema.retain(|(k, _)| *k != key);
+                schema.push((key, value));
+            }
+        }
+    }
+    schema
+}
+
+fn constraint_json_schema(constraint: &Constraint) -> JsonObject {
+    let int = |v: i128| match i64::try_from(v) {
+        Ok(v) => Json::Int(v),
+        Err(_) => Json::Float(v as f64),
+    };
+    match constraint {
+        Constraint::Range(start, end) => {
+            let mut schema = Vec::new();
+            match start {
+                Bound::Included(v) => schema.push(("minimum".into(), int(*v))),
+                Bound::Excluded(v) => schema.push(("exclusiveMinimum".into(), int(*v))),
+                Bound::Unbounded => {}
+            }
+            match end {
+                Bound::Included(v) => schema.push(("maximum".into(), int(*v))),
+                Bound::Excluded(v) => schema.push(("exclusiveMaximum".into(), int(*v))),
+                Bound::Unbounded => {}
+            }
+            schema
+        }
+        Constraint::Align(align) => vec![("multipleOf".into(), int(*align))],
+        Constraint::Pow2 => Vec::new(),
+        Constraint::Regex(re) => vec![("pattern".into(), Json::Str(re.as_str().into()))],
+    }
+}
+
+/// Returns the annotations of the metadata of the item (e.g., `deprecated`).
+fn metadata_annotations(item: &ConfigItem) -> Vec<String> {
+    let mut annotations = Vec::new();
+    match item.deprecated() {
+        Some("") => annotations.push("deprecated".into()),
+        Some(msg) => annotations.push(format!("deprecated({})", Value::from(msg))),
+        None => {}
+    }
+    if !item.renamed_from().is_empty() {
+        annotations.push(format!(
+            "renamed_from({})",
+            string_list(item.renamed_from())
+        ));
+    }
+    if let (Some(selector), None) = (item.selector(), item.variant()) {
+        annotations.push(format!("by({})", Value::from(selector)));
+    }
+    annotations
+}
+
+/// Returns the value of the item in TOML, which is the table of the value
+/// variants if they have not been resolved.
+fn toml_item_value(item: &ConfigItem) -> String {
+    match (item.selector(), item.variant()) {
+        (Some(_), None) => variants_toml(item.variants()),
+        _ => item.value().to_toml_value(),
+    }
 }
 
-fn const_name(name: &str) -> String {
-    name.to_uppercase().replace('-', "_")
+/// Returns the inline table of the value variants.
+fn variants_toml(variants: &[(String, ConfigValue)]) -> String {
+    let variants = variants
+        .iter()
+        .map(|(name, value)| format!("{} = {}", toml_key(name), value.to_toml_value()))
+        .collect::<Vec<_>>();
+    if variants.is_empty() {
+        "{}".into()
+    } else {
+        format!("{{ {} }}", variants.join(", "))
+    }
+}
+
+/// Returns the comma-separated TOML strings.
+fn string_list(items: &[String]) -> String {
+    let items = items.iter().map(|s| Value::from(s.as_str()).to_string());
+    items.collect::<Vec<_>>().join(", ")
+}
+
+fn toml_key(name: &str) -> String {
+    Key::new(name).display_repr().into()
+}
+
+/// Keywords that can be used as raw identifiers (e.g., `r#type`).
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// Converts a table name or a key into a valid Rust identifier.
+fn rust_ident(name: &str, case: IdentCase) -> String {
+    let mut ident = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => match case {
+                IdentCase::Upper => c.to_ascii_uppercase(),
+                IdentCase::Lower => c.to_ascii_lowercase(),
+                IdentCase::Preserve => c,
+            },
+            _ => '_',
+        })
+        .collect::<String>();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if RUST_KEYWORDS.contains(&ident.as_str()) {
+        format!("r#{}", ident)
+    } else if matches!(ident.as_str(), "_" | "crate" | "self" | "Self" | "super") {
+        // cannot be raw identifiers
+        ident + "_"
+    } else {
+        ident
+    }
 }
@@ -0,0 +1,214 @@
+use std::fmt;
+
+use crate::json::Json;
+
+/// A warning raised when updating a config with another one (see
+/// [`Config::update`](crate::Config::update)).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateWarning {
+    /// The item is not set in the other config, the default value is used.
+    ///
+    /// It is also raised for the items left out of a minimal config (see
+    /// [`Config::diff`](crate::Config::diff)), which the CLI skips with
+    /// `--minimal`.
+    Untouched {
+        /// The name of the item (`key` or `table.key`).
+        item: String,
+    },
+    /// The item is not found in the config, its value is ignored.
+    Extra {
+        /// The name of the item.
+        item: String,
+    },
+    /// The item is set by its old name, the value is migrated to the item
+    /// with the new name.
+    Migrated {
+        /// The old name of the item.
+        from: String,
+        /// The new name of the item.
+        to: String,
+    },
+    /// The value has a different type, and is coerced to the type of the item.
+    Coerced {
+        /// The name of the item.
+        item: String,
+        /// The value in TOML format.
+        value: String,
+        /// The type of the value.
+        from: String,
+        /// The type of the item.
+        to: String,
+    },
+    /// The value does not match the type of the item, the default value is
+    /// used.
+    Mismatched {
+        /// The name of the item.
+        item: String,
+        /// The value in TOML format.
+        value: String,
+        /// The type of the item.
+        ty: String,
+    },
+}
+
+impl UpdateWarning {
+    /// Returns the kind of the warning (e.g., `untouched`, `migrated`).
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Untouched { .. } => "untouched",
+            Self::Extra { .. } => "extra",
+            Self::Migrated { .. } => "migrated",
+            Self::Coerced { .. } => "coerced",
+            Self::Mismatched { .. } => "mismatched",
+        }
+    }
+
+    /// Returns the name of the item the warning is about. For migrated items,
+    /// it is the new name.
+    pub fn item(&self) -> &str {
+        match self {
+            Self::Untouched { item }
+            | Self::Extra { item }
+            | Self::Coerced { item, .. }
+            | Self::Mismatched { item, .. } => item,
+            Self::Migrated { to, .. } => to,
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let str = |s: &str| Json::Str(s.into());
+        let mut fields = vec![("kind".into(), str(self.kind()))];
+        match self {
+            Self::Untouched { item } | Self::Extra { item } => {
+                fields.push(("item".into(), str(item)));
+            }
+            Self::Migrated { from, to } => {
+                fields.push(("item".into(), str(to)));
+                fields.push(("from".into(), str(from)));
+            }
+            Self::Coerced {
+                item,
+                value,
+                from,
+                to,
+            } => {
+                fields.push(("item".into(), str(item)));
+                fields.push(("value".into(), str(value)));
+                fields.push(("from".into(), str(from)));
+                fields.push(("to".into(), str(to)));
+            }
+            Self::Mismatched { item, value, ty } => {
+                fields.push(("item".into(), str(item)));
+                fields.push(("value".into(), str(value)));
+                fields.push(("type".into(), str(ty)));
+            }
+        }
+        fields.push(("message".into(), Json::Str(self.to_string())));
+        Json::Object(fields)
+    }
+}
+
+impl fmt::Display for UpdateWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Untouched { item } => write!(
+                f,
+                "config item `{}` not set in the old config, using default value",
+                item
+            ),
+            Self::Extra { item } => write!(
+                f,
+                "config item `{}` not found in the specification, ignoring",
+                item
+            ),
+            Self::Migrated { from, to } => write!(
+                f,
+                "config item `{}` has been renamed to `{}`, migrating the value",
+                from, to
+            ),
+            Self::Coerced {
+                item,
+                value,
+                from,
+                to,
+            } => write!(
+                f,
+                "config item `{}` = {} of type `{}` is coerced to type `{}`",
+                item, value, from, to
+            ),
+            Self::Mismatched { item, value, ty } => write!(
+                f,
+                "config item `{}` = {} does not match type `{}`, using default value",
+                item, value, ty
+            ),
+        }
+    }
+}
+
+/// The report of updating a config with another one, containing all the
+/// warnings (see [`Config::update`](crate::Config::update)).
+///
+/// It can be rendered as text (one warning per line) by [`Display`], or as
+/// JSON by [`UpdateReport::to_json`].
+///
+/// [`Display`]: fmt::Display
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateReport {
+    warnings: Vec<UpdateWarning>,
+}
+
+impl UpdateReport {
+    pub(crate) fn push(&mut self, warning: UpdateWarning) {
+        self.warnings.push(warning);
+    }
+
+    /// Returns whether there are no warnings.
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Returns all the warnings, in the order they were raised.
+    pub fn warnings(&self) -> &[UpdateWarning] {
+        &self.warnings
+    }
+
+    /// Returns the names of the items not set in the other config.
+    pub fn untouched(&self) -> impl Iterator<Item = &str> {
+        self.of_kind("untouched")
+    }
+
+    /// Returns the names of the items not found in the config.
+    pub fn extra(&self) -> impl Iterator<Item = &str> {
+        self.of_kind("extra")
+    }
+
+    fn of_kind(&self, kind: &'static str) -> impl Iterator<Item = &str> {
+        self.warnings
+            .iter()
+            .filter(move |w| w.kind() == kind)
+            .map(|w| w.item())
+    }
+
+    /// Returns the JSON array of the warnings.
+    ///
+    /// Each warning is an object with the fields `kind`, `item`, `message`,
+    /// and the fields of the corresponding [`UpdateWarning`] variant.
+    pub fn to_json(&self) -> String {
+        Json::Array(self.warnings.iter().map(|w| w.to_json()).collect()).pretty(0)
+    }
+}
+
+impl Extend<UpdateWarning> for UpdateReport {
+    fn extend<T: IntoIterator<Item = UpdateWarning>>(&mut self, iter: T) {
+        self.warnings.extend(iter);
+    }
+}
+
+impl fmt::Display for UpdateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for w in &self.warnings {
+            writeln!(f, "{}", w)?;
+        }
+        Ok(())
+    }
+}
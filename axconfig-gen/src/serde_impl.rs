@@ -0,0 +1,422 @@
+//! [`serde`] support for [`Config`] and [`ConfigValue`], enabled by the
+//! `serde` feature.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{self, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, SeqAccess};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+use toml_edit::Value;
+
+use crate::value::{flags_value, value_to_int};
+use crate::{Config, ConfigErr, ConfigItem, ConfigResult, ConfigType, ConfigValue};
+
+impl de::Error for ConfigErr {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Other(msg.to_string())
+    }
+}
+
+impl ser::Error for ConfigErr {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Other(msg.to_string())
+    }
+}
+
+impl Config {
+    /// Deserializes the config into a user-defined type.
+    ///
+    /// Tables are deserialized as nested structs (or maps), and integers
+    /// written as strings (e.g., `"0xffff_ff80_0000_0000"`) as integers. The
+    /// `-` in keys is also matched with the `_` in struct field names.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use axconfig_gen::Config;
+    /// #[derive(serde::Deserialize)]
+    /// struct MyConfig {
+    ///     smp: usize,
+    ///     platform: MyPlatform,
+    /// }
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct MyPlatform {
+    ///     kernel_base_vaddr: u64,
+    ///     mmio_regions: Vec<(usize, usize)>,
+    /// }
+    ///
+    /// let config = Config::from_toml(r#"
+    /// smp = 4
+    /// [platform]
+    /// kernel-base-vaddr = "0xffff_ff80_0020_0000"     # uint
+    /// mmio-regions = [["0xfec0_0000", "0x1000"]]      # [(uint, uint)]
+    /// "#).unwrap();
+    /// let cfg: MyConfig = config.deserialize().unwrap();
+    /// assert_eq!(cfg.smp, 4);
+    /// assert_eq!(cfg.platform.kernel_base_vaddr, 0xffff_ff80_0020_0000);
+    /// assert_eq!(cfg.platform.mmio_regions, [(0xfec0_0000, 0x1000)]);
+    /// ```
+    pub fn deserialize<'de, T: de::Deserialize<'de>>(&'de self) -> ConfigResult<T> {
+        T::deserialize(self)
+    }
+}
+
+impl ConfigValue {
+    /// Deserializes the config value into a user-defined type.
+    ///
+    /// See [`Config::deserialize`] for how values are deserialized.
+    pub fn deserialize<'de, T: de::Deserialize<'de>>(&'de self) -> ConfigResult<T> {
+        T::deserialize(self)
+    }
+}
+
+/// An entry of a table to be deserialized, which is either an item or a
+/// (non-global) table.
+enum Entry<'de> {
+    Value(&'de ConfigValue),
+    Table(Vec<(&'de str, Entry<'de>)>),
+}
+
+impl<'de> Entry<'de> {
+    fn items(table: impl Iterator<Item = &'de ConfigItem>) -> Vec<(&'de str, Self)> {
+        table
+            .map(|item| (item.key(), Self::Value(item.value())))
+            .collect()
+    }
+}
+
+fn config_entries(config: &Config) -> Vec<(&str, Entry<'_>)> {
+    let mut entries = Vec::new();
+    for (name, table, _) in config.table_iter() {
+        if name == Config::GLOBAL_TABLE_NAME {
+            entries.extend(Entry::items(table.values()));
+        } else {
+            entries.push((name, Entry::Table(Entry::items(table.values()))));
+        }
+    }
+    entries
+}
+
+/// Accesses the entries of a table as a map, the keys are renamed to the
+/// struct fields with `_` in place of `-` if there are such fields.
+struct TableAccess<'de> {
+    entries: std::vec::IntoIter<(&'de str, Entry<'de>)>,
+    fields: &'static [&'static str],
+    next: Option<Entry<'de>>,
+}
+
+impl<'de> TableAccess<'de> {
+    fn new(entries: Vec<(&'de str, Entry<'de>)>, fields: &'static [&'static str]) -> Self {
+        Self {
+            entries: entries.into_iter(),
+            fields,
+            next: None,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for TableAccess<'de> {
+    type Error = ConfigErr;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> ConfigResult<Option<K::Value>> {
+        let Some((key, entry)) = self.entries.next() else {
+            return Ok(None);
+        };
+        self.next = Some(entry);
+        if !self.fields.contains(&key) {
+            if let Some(field) = self.fields.iter().find(|f| **f == key.replace('-', "_")) {
+                return seed.deserialize(field.into_deserializer()).map(Some);
+            }
+        }
+        seed.deserialize(BorrowedStrDeserializer::new(key))
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> ConfigResult<V::Value> {
+        match self.next.take() {
+            Some(Entry::Value(value)) => seed.deserialize(value),
+            Some(Entry::Table(entries)) => seed.deserialize(TableDeserializer(entries)),
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+}
+
+struct TableDeserializer<'de>(Vec<(&'de str, Entry<'de>)>);
+
+impl<'de> Deserializer<'de> for TableDeserializer<'de> {
+    type Error = ConfigErr;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        visitor.visit_map(TableAccess::new(self.0, &[]))
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> ConfigResult<V::Value> {
+        visitor.visit_map(TableAccess::new(self.0, fields))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+impl<'de> Deserializer<'de> for &'de Config {
+    type Error = ConfigErr;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        TableDeserializer(config_entries(self)).deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> ConfigResult<V::Value> {
+        TableDeserializer(config_entries(self)).deserialize_struct(name, fields, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+impl<'de> Deserializer<'de> for &'de ConfigValue {
+    type Error = ConfigErr;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        ValueDeserializer::new(self.raw(), self.ty()).deserialize_any(visitor)
+    }
+
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        ValueDeserializer::new(self.raw(), self.ty()).deserialize_str(visitor)
+    }
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        ValueDeserializer::new(self.raw(), self.ty()).deserialize_option(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> ConfigResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> ConfigResult<V::Value> {
+        ValueDeserializer::new(self.raw(), self.ty()).deserialize_enum(name, variants, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Deserializes a raw TOML value of the specified type (if any).
+struct ValueDeserializer<'de> {
+    value: &'de Value,
+    ty: Option<&'de ConfigType>,
+}
+
+impl<'de> ValueDeserializer<'de> {
+    fn new(value: &'de Value, ty: Option<&'de ConfigType>) -> Self {
+        Self { value, ty }
+    }
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = ConfigErr;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        if let Some(ConfigType::Alias(_, ty) | ConfigType::Mapped(ty, ..)) = self.ty {
+            return ValueDeserializer::new(self.value, Some(ty)).deserialize_any(visitor);
+        }
+        if let Some(ConfigType::Option(ty)) = self.ty {
+            return match self.value.as_str() {
+                Some("none") => visitor.visit_none(),
+                _ => ValueDeserializer::new(self.value, Some(ty)).deserialize_any(visitor),
+            };
+        }
+        if let Some(ConfigType::Flags(flags)) = self.ty {
+            let v = flags_value(self.value, flags).ok_or(ConfigErr::ValueTypeMismatch)?;
+            return visitor.visit_u64(v);
+        }
+        match self.value {
+            Value::Boolean(b) => visitor.visit_bool(*b.value()),
+            Value::Float(f) => visitor.visit_f64(*f.value()),
+            Value::Integer(i) if matches!(self.ty, Some(ConfigType::F32 | ConfigType::F64)) => {
+                visitor.visit_f64(*i.value() as f64)
+            }
+            Value::String(s) if matches!(self.ty, Some(ConfigType::String | ConfigType::Char)) => {
+                visitor.visit_borrowed_str(s.value())
+            }
+            Value::Integer(_) | Value::String(_) => match value_to_int(self.value) {
+                Some(v) => match (i64::try_from(v), u64::try_from(v)) {
+                    (Ok(v), _) => visitor.visit_i64(v),
+                    (_, Ok(v)) => visitor.visit_u64(v),
+                    _ if v < 0 => visitor.visit_i128(v),
+                    _ => visitor.visit_u128(v as u128),
+                },
+                None => visitor.visit_borrowed_str(self.value.as_str().unwrap()),
+            },
+            Value::Array(arr) => {
+                let elems = arr.iter().enumerate().map(|(i, v)| {
+                    let ty = match self.ty {
+                        Some(ConfigType::Array(ty) | ConfigType::FixedArray(ty, _)) => {
+                            Some(ty.as_ref())
+                        }
+                        Some(ConfigType::Tuple(types)) => types.get(i),
+                        _ => None,
+                    };
+                    ValueDeserializer::new(v, ty)
+                });
+                visitor.visit_seq(ElemAccess(elems))
+            }
+            _ => Err(ConfigErr::InvalidValue),
+        }
+    }
+
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        match self.value {
+            Value::String(s) => visitor.visit_borrowed_str(s.value()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> ConfigResult<V::Value> {
+        match (self.ty.map(ConfigType::resolved), self.value.as_str()) {
+            (Some(ConfigType::Option(_)), Some("none")) => visitor.visit_none(),
+            (Some(ConfigType::Option(ty)), _) => {
+                visitor.visit_some(ValueDeserializer::new(self.value, Some(ty)))
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> ConfigResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> ConfigResult<V::Value> {
+        match self.value {
+            Value::String(s) => visitor.visit_enum(BorrowedStrDeserializer::new(s.value())),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct ElemAccess<I>(I);
+
+impl<'de, I: Iterator<Item = ValueDeserializer<'de>>> SeqAccess<'de> for ElemAccess<I> {
+    type Error = ConfigErr;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> ConfigResult<Option<T::Value>> {
+        self.0.next().map(|d| seed.deserialize(d)).transpose()
+    }
+}
+
+impl Serialize for Config {
+    /// Serializes the config as a map of the global items and the tables,
+    /// which can be serialized back to TOML.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (name, table, _) in self.table_iter() {
+            if name == Config::GLOBAL_TABLE_NAME {
+                for (key, item) in table {
+                    map.serialize_entry(key, item.value())?;
+                }
+            } else {
+                map.serialize_entry(name, &TableSerializer(table))?;
+            }
+        }
+        map.end()
+    }
+}
+
+struct TableSerializer<'a>(&'a BTreeMap<String, ConfigItem>);
+
+impl Serialize for TableSerializer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(key, item)| (key, item.value())))
+    }
+}
+
+impl Serialize for ConfigValue {
+    /// Serializes the config value as is, i.e., integers written as strings
+    /// are kept as strings.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_value(self.raw(), serializer)
+    }
+}
+
+fn serialize_value<S: Serializer>(value: &Value, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Value::Boolean(b) => serializer.serialize_bool(*b.value()),
+        Value::Integer(i) => serializer.serialize_i64(*i.value()),
+        Value::Float(f) => serializer.serialize_f64(*f.value()),
+        Value::String(s) => serializer.serialize_str(s.value()),
+        Value::Array(arr) => {
+            let mut seq = serializer.serialize_seq(Some(arr.len()))?;
+            for v in arr {
+                seq.serialize_element(&RawValue(v))?;
+            }
+            seq.end()
+        }
+        _ => Err(ser::Error::custom("invalid config value")),
+    }
+}
+
+struct RawValue<'a>(&'a Value);
+
+impl Serialize for RawValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_value(self.0, serializer)
+    }
+}
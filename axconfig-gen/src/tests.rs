@@ -1,4 +1,7 @@
-use crate::{Config, ConfigErr, ConfigResult, ConfigType, ConfigValue, OutputFormat};
+use crate::{
+    ArrayLen, Config, ConfigErr, ConfigResult, ConfigSource, ConfigType, ConfigValue, Constraint,
+    DumpOptions, IdentCase, OutputFormat, UpdateReport, UpdateWarning,
+};
 
 fn check_type_infer(value: &str, expect_ty: &str) -> ConfigResult<()> {
     let value = ConfigValue::new(value)?;
@@ -131,7 +134,7 @@ fn test_err() {
     assert_err!(ConfigType::new("(()())"), InvalidType);
     assert!(ConfigType::new("((),())").is_ok());
     assert!(ConfigType::new("(  )").is_ok());
-    assert_err!(ConfigValue::new("233.0"), InvalidValue);
+    assert_err!(ConfigValue::new("{ a = 233 }"), InvalidValue);
 }
 
 #[test]
@@ -179,6 +182,29 @@ fn test_to_rust() {
     assert_eq!(value.to_rust_value(&ty, 0).unwrap(), rust);
 }
 
+#[test]
+fn test_rust_strings() {
+    let check = |toml: &str, rust: &str| {
+        let value = ConfigValue::new(toml).unwrap();
+        let ty = ConfigType::new("str").unwrap();
+        assert_eq!(value.to_rust_value(&ty, 0).unwrap(), rust, "{}", toml);
+    };
+    // basic strings
+    check(r#""hello""#, r#""hello""#);
+    check(r#""tab\there""#, r#""tab\there""#);
+    check(r#""say \"hi\"""#, r##"r#"say "hi""#"##);
+    check(r#""\U0001F600 \u00e9""#, "\"\u{1F600} \u{e9}\"");
+    check(r#""esc\u001b[0m""#, r#""esc\u{1b}[0m""#);
+    // literal strings
+    check(r#"'C:\path\to'"#, r#"r"C:\path\to""#);
+    check(r##"'"#"'"##, r###"r##""#""##"###);
+    // multi-line basic strings
+    check("\"\"\"\nline 1\nline 2\"\"\"", r#""line 1\nline 2""#);
+    check("\"\"\"a \\\n    b\"\"\"", r#""a b""#);
+    // multi-line literal strings
+    check("'''\nC:\\dir\n\\n'''", r#""C:\\dir\n\\n""#);
+}
+
 #[test]
 fn integration_test() -> std::io::Result<()> {
     let spec = std::fs::read_to_string("../example-configs/defconfig.toml")?;
@@ -189,3 +215,2341 @@ fn integration_test() -> std::io::Result<()> {
     assert_eq!(cfg.dump(OutputFormat::Rust).unwrap(), rust);
     Ok(())
 }
+
+#[test]
+fn test_diff() {
+    let spec = Config::from_toml(
+        r#"
+        smp = 1     # uint
+        arch = "x86_64"
+
+        # Kernel configs
+        [kernel]
+        # Stack size of each task.
+        task-stack-size = 0x1000    # uint
+        ticks-per-sec = 100         # uint
+
+        [platform]
+        phys-memory-size = "0x800_0000" # uint
+        mmio-regions = [[0xb000_0000, 0x1000]]  # [(uint, uint)]
+        "#,
+    )
+    .unwrap();
+    let full = Config::from_toml(
+        r#"
+        smp = 4
+        arch = "x86_64"
+
+        # Kernel configs
+        [kernel]
+        # Stack size of each task.
+        task-stack-size = 0x40000   # uint
+        ticks-per-sec = "100"       # uint
+
+        [platform]
+        phys-memory-size = 0x800_0000   # uint
+        mmio-regions = [["0xb000_0000", "0x1000"]]  # [(uint, uint)]
+        "#,
+    )
+    .unwrap();
+
+    let minimal = full.diff(&spec).unwrap();
+    assert_eq!(
+        minimal.dump_toml().unwrap(),
+        r#"smp = 4
+
+# Kernel configs
+[kernel]
+# Stack size of each task.
+task-stack-size = 0x40000 # uint
+"#
+    );
+
+    let mut rebuilt = spec.clone();
+    let report = rebuilt.update(&minimal).unwrap();
+    assert!(rebuilt.diff(&full).unwrap().is_empty());
+    // the items left out are reported as untouched, and nothing else
+    assert_eq!(
+        report.untouched().collect::<Vec<_>>(),
+        [
+            "arch",
+            "kernel.ticks-per-sec",
+            "platform.mmio-regions",
+            "platform.phys-memory-size"
+        ]
+    );
+    assert_eq!(report.warnings().len(), 4);
+    assert!(full.diff(&full).unwrap().is_empty());
+}
+
+#[test]
+fn test_overlay() {
+    let layer = |toml: &str, name: &str, path: &str| {
+        let mut cfg = Config::from_toml(toml).unwrap();
+        cfg.set_source(ConfigSource::new(name, Some(path)));
+        cfg
+    };
+    let mut config = layer(
+        "smp = 1 # uint\n[kernel]\nticks-per-sec = 100 # uint\n",
+        "spec",
+        "defconfig.toml",
+    );
+    config
+        .overlay(&layer("smp = 2\n", "arch", "arch.toml"))
+        .unwrap();
+    config
+        .overlay(&layer(
+            "smp = 4\n[kernel]\nticks-per-sec = 1000\n",
+            "board",
+            "board.toml",
+        ))
+        .unwrap();
+    config
+        .config_at_mut(Config::GLOBAL_TABLE_NAME, "smp")
+        .unwrap()
+        .update_from(
+            ConfigValue::new("8").unwrap(),
+            ConfigSource::new("command line", None),
+        )
+        .unwrap();
+
+    let smp = config.config_at(Config::GLOBAL_TABLE_NAME, "smp").unwrap();
+    let history = smp
+        .history()
+        .iter()
+        .map(|(source, value)| format!("{}: {}", source, value.to_toml_value()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        history,
+        [
+            "spec (defconfig.toml): 1",
+            "arch (arch.toml): 2",
+            "board (board.toml): 4",
+            "command line: 8",
+        ]
+    );
+    assert_eq!(smp.source().unwrap().layer(), "command line");
+
+    let ticks = config.config_at("kernel", "ticks-per-sec").unwrap();
+    assert_eq!(ticks.value().to_toml_value(), "1000");
+    assert_eq!(ticks.source().unwrap().path(), Some("board.toml"));
+
+    assert!(config
+        .overlay(&layer("nr-cpus = 4\n", "user", "user.toml"))
+        .is_err());
+    // a failed overlay leaves the config unchanged
+    assert!(config
+        .overlay(&layer("smp = 16\nnr-cpus = 4\n", "user", "user.toml"))
+        .is_err());
+    assert_eq!(config.get::<u64>("smp").unwrap(), 8);
+    assert_err!(
+        config.overlay(&layer("smp = \"abc\"\n", "user", "user.toml")),
+        ValueTypeMismatch
+    );
+}
+
+#[test]
+fn test_apply_env() {
+    let mut config = Config::from_toml(
+        r#"
+        smp = 1         # uint
+        arch = "x86_64" # str
+        [kernel]
+        task-stack-size = 0x1000    # uint
+        "#,
+    )
+    .unwrap();
+    let item = config.config_at("kernel", "task-stack-size").unwrap();
+    assert_eq!(
+        item.env_name("AX_CONFIG_"),
+        "AX_CONFIG_KERNEL__TASK_STACK_SIZE"
+    );
+
+    let env = [
+        ("SMP", "4"),
+        ("ARCH", "aarch64"),
+        ("KERNEL__TASK_STACK_SIZE", "\"0x4_0000\""),
+    ];
+    let mut consulted = Vec::new();
+    let overridden = config
+        .apply_env("", |name| {
+            consulted.push(name.to_string());
+            env.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        })
+        .unwrap();
+    assert_eq!(consulted, ["ARCH", "SMP", "KERNEL__TASK_STACK_SIZE"]);
+    assert_eq!(overridden, ["arch", "smp", "kernel.task-stack-size"]);
+    assert_eq!(
+        config.dump_toml().unwrap(),
+        "arch = \"aarch64\" # str\nsmp = 4 # uint\n\n[kernel]\ntask-stack-size = \"0x4_0000\" # uint\n"
+    );
+    let smp = config.config_at(Config::GLOBAL_TABLE_NAME, "smp").unwrap();
+    assert_eq!(smp.source().unwrap().path(), Some("SMP"));
+
+    let res = config.apply_env("AX_", |name| (name == "AX_SMP").then(|| "abc".into()));
+    assert_eq!(
+        res.unwrap_err().to_string(),
+        "Invalid value `abc` of environment variable `AX_SMP`: Config value and type mismatch"
+    );
+    // the overrides before the invalid value are not applied
+    let before = config.clone();
+    let res = config.apply_env("AX_", |name| match name {
+        "AX_ARCH" => Some("riscv64".into()),
+        "AX_SMP" => Some("abc".into()),
+        _ => None,
+    });
+    assert!(res.is_err());
+    assert_eq!(config.get::<String>("arch").unwrap(), "aarch64");
+    let arch = |c: &Config| {
+        c.config_at(Config::GLOBAL_TABLE_NAME, "arch")
+            .unwrap()
+            .history()
+            .len()
+    };
+    assert_eq!(arch(&config), arch(&before));
+
+    // numbers and booleans are taken as plain strings for `str` items
+    let res = config.apply_env("", |name| (name == "ARCH").then(|| "1234".into()));
+    assert_eq!(res.unwrap(), ["arch"]);
+    assert_eq!(config.get::<String>("arch").unwrap(), "1234");
+
+    // items mapped to the same variable
+    let mut config = Config::from_toml("a-b = 1\na_b = 2\n").unwrap();
+    assert!(config.apply_env("", |_| None).is_err());
+    let mut config = Config::from_toml("foo__bar = 1\n[foo]\nbar = 2\n").unwrap();
+    assert_eq!(
+        config.apply_env("AX_", |_| None).unwrap_err().to_string(),
+        "Config items `foo__bar` and `foo.bar` map to the same environment variable `AX_FOO__BAR`"
+    );
+}
+
+#[test]
+fn test_constraints() {
+    macro_rules! check {
+        ($value:expr, $ty:expr, $constraint:expr) => {{
+            let mut value = ConfigValue::new_with_type($value, $ty).unwrap();
+            value.set_constraints(vec![Constraint::new($constraint).unwrap()])
+        }};
+    }
+    macro_rules! assert_violation {
+        ($res:expr, $constraint:expr) => {
+            match $res {
+                Err(ConfigErr::ConstraintViolation { constraint, .. }) => {
+                    assert_eq!(constraint, $constraint)
+                }
+                res => panic!("expected constraint violation, got `{:?}`", res),
+            }
+        };
+    }
+
+    check!("1", "uint", "range(1..=256)").unwrap();
+    check!("256", "uint", "range(1..=256)").unwrap();
+    check!("\"0x100\"", "uint", "range(..=0x100)").unwrap();
+    check!("-5", "int", "range(-8..0)").unwrap();
+    assert_violation!(check!("0", "uint", "range(1..=256)"), "range(1..=256)");
+    assert_violation!(check!("257", "uint", "range(1..=256)"), "range(1..=256)");
+    assert_violation!(check!("0", "int", "range(-8..0)"), "range(-8..0)");
+    assert_violation!(check!("0", "uint", " range( 1.. ) "), "range(1..)");
+
+    check!("0x4_0000", "uint", "align(0x1000)").unwrap();
+    check!(
+        "[[0x1000, \"a\"], [0, \"b\"]]",
+        "[(uint, str)]",
+        "align(4096)"
+    )
+    .unwrap();
+    assert_violation!(check!("0x4_0010", "uint", "align(0x1000)"), "align(0x1000)");
+    assert_violation!(
+        check!(
+            "[[0x1000, 1], [0x10, 2]]",
+            "[(uint, uint)]",
+            "align(0x1000)"
+        ),
+        "align(0x1000)"
+    );
+
+    check!("1", "uint", "pow2").unwrap();
+    check!("\"0x8000_0000\"", "uint", "pow2").unwrap();
+    assert_violation!(check!("0", "uint", "pow2"), "pow2");
+    assert_violation!(check!("6", "uint", "pow2"), "pow2");
+
+    check!("\"x86_64-qemu-q35\"", "str", r#"regex("^[a-z0-9_-]+$")"#).unwrap();
+    assert_violation!(
+        check!("\"X86\"", "str", r#"regex("^[a-z0-9_-]+$")"#),
+        r#"regex("^[a-z0-9_-]+$")"#
+    );
+
+    assert!(check!("\"abc\"", "str", "pow2").is_err());
+    assert!(check!("1", "uint", "regex(\"a\")").is_err());
+    for s in [
+        "range(1)",
+        "align(0)",
+        "regex(abc)",
+        "regex(\"(\")",
+        "pow3",
+        "foo(1)",
+        "range(--8..0)",
+        "range(-+8..0)",
+    ] {
+        assert!(Constraint::new(s).is_err(), "{}", s);
+    }
+    // at most one sign is allowed
+    use crate::value::parse_int;
+    assert_eq!(parse_int("-5"), Some(-5));
+    assert_eq!(parse_int("-0x10"), Some(-16));
+    assert_eq!(parse_int("+5"), Some(5));
+    for s in ["--5", "-+5", "+-5", "0x-5", "-0x+5"] {
+        assert_eq!(parse_int(s), None, "{}", s);
+    }
+
+    let spec = r#"
+    smp = 4     # uint, range(1..=256)
+    [kernel]
+    task-stack-size = 0x4_0000  # uint, align(0x1000), pow2
+    plat = "x86_64-qemu-q35"    # str, regex("^[a-z0-9_-]+$")
+    "#;
+    let mut config = Config::from_toml(spec).unwrap();
+    let dumped = config.dump_toml().unwrap();
+    assert_eq!(
+        dumped,
+        r#"smp = 4 # uint, range(1..=256)
+
+[kernel]
+plat = "x86_64-qemu-q35" # str, regex("^[a-z0-9_-]+$")
+task-stack-size = 0x4_0000 # uint, align(0x1000), pow2
+"#
+    );
+    assert_eq!(
+        Config::from_toml(&dumped).unwrap().dump_toml().unwrap(),
+        dumped
+    );
+
+    let item = config.config_at_mut("kernel", "task-stack-size").unwrap();
+    assert_violation!(
+        item.value_mut().update(ConfigValue::new("0x3000").unwrap()),
+        "pow2"
+    );
+    item.value_mut()
+        .update(ConfigValue::new("0x8000").unwrap())
+        .unwrap();
+    assert_violation!(
+        config.update(&Config::from_toml("smp = 0").unwrap()),
+        "range(1..=256)"
+    );
+    assert!(Config::from_toml("smp = 0 # uint, range(1..)").is_err());
+}
+
+#[test]
+fn test_expr() {
+    use crate::expr::{Expr, ExprValue};
+
+    let lookup = |name: &str| match name {
+        "smp" => Ok(ExprValue::Int(4)),
+        "arch" => Ok(ExprValue::Str("aarch64".into())),
+        "plat.regions" => Ok(ExprValue::List(vec![
+            ExprValue::List(vec![ExprValue::Int(0x1000), ExprValue::Int(0x1000)]),
+            ExprValue::List(vec![ExprValue::Int(0x3000), ExprValue::Int(0x1000)]),
+        ])),
+        // regions at the end of the 64-bit address space
+        "plat.top" | "plat.wrapping" | "plat.overflowing" => {
+            let (base, size) = match name {
+                "plat.top" => (0xffff_ffff_ffff_f000, 0x1000),
+                "plat.wrapping" => (0xffff_ffff_ffff_f000, 0x2000),
+                _ => (i128::MAX, 1),
+            };
+            let region = vec![ExprValue::Int(base), ExprValue::Int(size)];
+            Ok(ExprValue::List(vec![ExprValue::List(region)]))
+        }
+        _ => Err(ConfigErr::Other(format!("`{}` not found", name))),
+    };
+    let eval = |s: &str| Expr::parse(s).unwrap().eval(&lookup);
+    macro_rules! check_eval {
+        ($expr:expr, $res:expr) => {
+            assert_eq!(eval($expr).unwrap(), $res, "{}", $expr);
+        };
+    }
+    use ExprValue::*;
+
+    check_eval!("1 + 2 * 3", Int(7));
+    check_eval!("(1 + 2) * 3", Int(9));
+    check_eval!("0x1000 - 1 & 0xff", Int(0xff));
+    check_eval!("1 << 12 | 0x1", Int(0x1001));
+    check_eval!("-smp + 1", Int(-3));
+    check_eval!("10 / 3 + 10 % 3", Int(4));
+    check_eval!("smp >= 1 && smp <= 256", Bool(true));
+    check_eval!("!(smp == 4) || false", Bool(false));
+    check_eval!("arch == 'aarch64' && arch != \"x86_64\"", Bool(true));
+    check_eval!("arch + \"-qemu\"", Str("aarch64-qemu".into()));
+    check_eval!("len(plat.regions) == 2", Bool(true));
+    check_eval!("plat.regions[1][0]", Int(0x3000));
+    check_eval!("max(smp, 8, 2) - min(smp, 8, 2)", Int(6));
+    check_eval!("no_overlap(plat.regions)", Bool(true));
+    check_eval!("no_overlap(plat.top)", Bool(true));
+    check_eval!("no_overlap(plat.wrapping)", Bool(false));
+    check_eval!("no_overlap(plat.overflowing)", Bool(false));
+    check_eval!("false && unknown", Bool(false));
+    check_eval!("0xffff_ffff_ffff_ffff + 1", Int(1 << 64));
+
+    assert!(eval("unknown + 1").is_err());
+    assert!(eval("smp + arch").is_err());
+    assert!(eval("plat.regions[2]").is_err());
+    assert!(eval("1 / 0").is_err());
+    assert!(eval("foo(1)").is_err());
+    for s in ["1 +", "(1", "1 2", "a ==", "'abc", "1 $ 2", ""] {
+        assert!(Expr::parse(s).is_err(), "{}", s);
+    }
+    assert_eq!(
+        Expr::parse("a-b - c.d-e + f").unwrap().idents(),
+        ["a-b", "c.d-e", "f"]
+    );
+}
+
+#[test]
+fn test_rules() {
+    let spec = r#"
+    smp = 4         # uint
+    arch = "x86_64" # str
+
+    [platform]
+    kernel-base-paddr = 0x20_0000                   # uint
+    kernel-base-vaddr = "0xffff_ff80_0020_0000"     # uint
+    phys-virt-offset = "0xffff_ff80_0000_0000"      # uint
+    kernel-aspace-base = "0xffff_ff80_0000_0000"    # uint
+    kernel-aspace-size = "0x0000_007f_ffff_f000"    # uint
+    mmio-regions = [[0xb000_0000, 0x1000_0000], [0xfec0_0000, 0x1000]]   # [(uint, uint)]
+
+    [__rules__]
+    smp-range = "smp >= 1 && smp <= 256"
+    kernel-vaddr = "platform.kernel-base-vaddr == platform.phys-virt-offset + platform.kernel-base-paddr"
+    mmio-no-overlap = "no_overlap(platform.mmio-regions)"
+    aspace-no-overflow = "platform.kernel-aspace-base + platform.kernel-aspace-size <= 0xffff_ffff_ffff_ffff"
+    "#;
+    let mut config = Config::from_toml(spec).unwrap();
+    assert_eq!(config.rules().count(), 4);
+    assert!(config.table_at(Config::RULES_TABLE_NAME).is_none());
+    config.check_rules().unwrap();
+
+    config
+        .update(
+            &Config::from_toml(
+                r#"
+                [platform]
+                kernel-base-paddr = 0x40_0000
+                mmio-regions = [[0xb000_0000, 0x1000_0000], [0xb000_1000, 0x1000]]
+                "#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    let err = config.check_rules().unwrap_err().to_string();
+
+    // rules are kept in the TOML output
+    let toml = config.dump_toml().unwrap();
+    assert!(toml.contains("\n[__rules__]\n"));
+    assert!(toml.contains("\nsmp-range = \"smp >= 1 && smp <= 256\"\n"));
+    let regenerated = Config::from_toml(&toml).unwrap();
+    assert_eq!(regenerated.rules().count(), 4);
+    assert_eq!(regenerated.check_rules().unwrap_err().to_string(), err);
+
+    assert_eq!(
+        err,
+        r#"Rule `kernel-vaddr` failed: platform.kernel-base-vaddr == platform.phys-virt-offset + platform.kernel-base-paddr
+    platform.kernel-base-vaddr = "0xffff_ff80_0020_0000"
+    platform.phys-virt-offset = "0xffff_ff80_0000_0000"
+    platform.kernel-base-paddr = 0x40_0000
+Rule `mmio-no-overlap` failed: no_overlap(platform.mmio-regions)
+    platform.mmio-regions = [
+        [0xb000_0000, 0x1000_0000],
+        [0xb000_1000, 0x1000]
+    ]"#
+    );
+
+    let mut other = Config::from_toml("[__rules__]\nsmp-range = \"smp > 0\"").unwrap();
+    assert!(config.merge(&other).is_err());
+    other.add_rule("arch", "arch == 'riscv64'").unwrap();
+    assert!(other.add_rule("arch", "true").is_err());
+    assert!(Config::from_toml("[__rules__]\nfoo = 1").is_err());
+    assert!(Config::from_toml("[__rules__]\nfoo = \"1 +\"").is_err());
+
+    let mut config = Config::from_toml("smp = 1").unwrap();
+    config.add_rule("not-bool", "smp + 1").unwrap();
+    assert!(config.check_rules().is_err());
+}
+
+#[test]
+fn test_depends_on() {
+    let spec = r#"
+    arch = "x86_64" # str
+
+    [devices]
+    pci-ecam-base = 0xb000_0000 # uint
+    gic-version = 3             # uint
+
+    [gic]
+    base = 0x800_0000           # uint
+
+    [__depends_on__]
+    "devices.pci-ecam-base" = "arch == 'x86_64'"
+    "devices.gic-version" = "arch == 'aarch64'"
+    gic = "arch == 'aarch64'"
+    "#;
+    let mut config = Config::from_toml(spec).unwrap();
+    assert_eq!(config.dependencies().count(), 3);
+    assert!(config.table_at(Config::DEPENDS_ON_TABLE_NAME).is_none());
+
+    let disabled = config
+        .iter()
+        .filter_map(|item| {
+            let cond = config.disabled_by(item).unwrap()?;
+            Some(format!("{}: {}", item.item_name(), cond))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        disabled,
+        [
+            "devices.gic-version: arch == 'aarch64'",
+            "gic.base: arch == 'aarch64'"
+        ]
+    );
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        r#"pub const ARCH: &str = "x86_64";
+
+pub mod devices {
+    pub const PCI_ECAM_BASE: usize = 0xb000_0000;
+}
+"#
+    );
+    // all items and conditions are kept in the TOML output
+    let toml = config.dump_toml().unwrap();
+    assert_eq!(toml.matches(" # uint").count(), 3);
+    let regenerated = Config::from_toml(&toml).unwrap();
+    assert_eq!(regenerated.dependencies().count(), 3);
+    assert_eq!(regenerated.dump_rs().unwrap(), config.dump_rs().unwrap());
+
+    let config_toml = "a = 1\n[dev]\nb = 2\n[__depends_on__]\ndev = \"a == 0\"\n";
+    let toml = Config::from_toml(config_toml).unwrap().dump_toml().unwrap();
+    assert_eq!(
+        toml,
+        "a = 1\n\n[dev]\nb = 2\n\n[__depends_on__]\ndev = \"a == 0\"\n"
+    );
+    assert_eq!(
+        Config::from_toml(&toml).unwrap().dump_rs().unwrap(),
+        "pub const A: usize = 1;\n"
+    );
+
+    // disabled items are not reported as untouched
+    let report = config
+        .update(&Config::from_toml("arch = \"x86_64\"").unwrap())
+        .unwrap();
+    assert_eq!(
+        report.untouched().collect::<Vec<_>>(),
+        ["devices.pci-ecam-base"]
+    );
+    assert_eq!(report.extra().count(), 0);
+
+    config
+        .update(&Config::from_toml("arch = \"aarch64\"").unwrap())
+        .unwrap();
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        r#"pub const ARCH: &str = "aarch64";
+
+pub mod devices {
+    pub const GIC_VERSION: usize = 3;
+}
+
+pub mod gic {
+    pub const BASE: usize = 0x800_0000;
+}
+"#
+    );
+
+    let mut config = Config::from_toml("smp = 1").unwrap();
+    config.add_dependency("smp", "nr-cpus > 1").unwrap();
+    assert!(config.dump_rs().is_err());
+    assert!(config.add_dependency("smp", "true").is_err());
+
+    // invalid conditions fail the checks instead of disabling them
+    config.add_rule("smp", "smp == 1").unwrap();
+    assert_eq!(
+        config.check_rules().unwrap_err().to_string(),
+        "Condition of `smp` failed: Failed to evaluate `nr-cpus > 1`: config item `nr-cpus` not found"
+    );
+    let config = Config::from_toml(
+        "smp = 1
+[__depends_on__]
+smp = \"smp\"",
+    )
+    .unwrap();
+    assert_eq!(
+        config.check_rules().unwrap_err().to_string(),
+        "Condition of `smp` must evaluate to a boolean, got `0x1`"
+    );
+}
+
+#[test]
+fn test_schema() {
+    let spec = r#"
+    # Number of CPUs.
+    smp = 1                     # uint, range(1..=256)
+
+    #
+    # Kernel configs
+    #
+    [kernel]
+    # Stack size of each task.
+    task-stack-size = 0x40000   # uint, align(0x1000)
+    # Number of timer ticks per second (Hz). A timer tick may contain several timer
+    # interrupts.
+    ticks-per-sec = 100         # uint
+
+    [__rules__]
+    smp = "smp <= 8"
+    "#;
+    let schema = Config::from_toml(spec)
+        .unwrap()
+        .dump(OutputFormat::Schema)
+        .unwrap();
+    assert_eq!(
+        schema,
+        r#"[smp]
+description = "Number of CPUs."
+type = "uint"
+default = 1
+constraints = ["range(1..=256)"]
+
+[kernel]
+description = "Kernel configs"
+
+[kernel.task-stack-size]
+description = "Stack size of each task."
+type = "uint"
+default = 0x40000
+constraints = ["align(0x1000)"]
+
+[kernel.ticks-per-sec]
+description = """
+Number of timer ticks per second (Hz). A timer tick may contain several timer
+interrupts."""
+type = "uint"
+default = 100
+
+[__rules__]
+smp = "smp <= 8"
+"#
+    );
+
+    let mut config = Config::from_schema(&schema).unwrap();
+    assert_eq!(config.rules().count(), 1);
+    assert_eq!(config.dump(OutputFormat::Schema).unwrap(), schema);
+    assert_eq!(
+        config.dump_toml().unwrap(),
+        r#"# Number of CPUs.
+smp = 1 # uint, range(1..=256)
+
+# Kernel configs
+[kernel]
+# Stack size of each task.
+task-stack-size = 0x40000 # uint, align(0x1000)
+# Number of timer ticks per second (Hz). A timer tick may contain several timer
+# interrupts.
+ticks-per-sec = 100 # uint
+
+[__rules__]
+smp = "smp <= 8"
+"#
+    );
+
+    // missing keys fall back to defaults, unknown keys are errors
+    let values = Config::from_toml("smp = 4\n[kernel]\ntask-stack-size = \"0x8000\"").unwrap();
+    config.overlay(&values).unwrap();
+    assert_eq!(
+        config
+            .config_at("kernel", "ticks-per-sec")
+            .unwrap()
+            .value()
+            .to_toml_value(),
+        "100"
+    );
+    assert!(config
+        .overlay(&Config::from_toml("[kernel]\nstack-size = 1").unwrap())
+        .is_err());
+    assert_err!(
+        config.overlay(&Config::from_toml("smp = \"abc\"").unwrap()),
+        ValueTypeMismatch
+    );
+
+    let schema = r#"
+    [old-smp]
+    type = "uint"
+    default = 1
+    deprecated = "use `smp` instead"
+    [kernel.type]
+    type = "str"
+    default = "monolithic"
+    deprecated = true
+    "#;
+    let config = Config::from_schema(schema).unwrap();
+    let old = config
+        .config_at(Config::GLOBAL_TABLE_NAME, "old-smp")
+        .unwrap();
+    assert_eq!(old.deprecated(), Some("use `smp` instead"));
+    assert_eq!(
+        config.config_at("kernel", "type").unwrap().deprecated(),
+        Some("")
+    );
+
+    for schema in [
+        "smp = 1",
+        "[smp]\ntype = \"uint\"",
+        "[smp]\ndefault = 1\ntype = 1",
+        "[smp]\ntype = \"uint\"\ndefault = \"abc\"",
+        "[smp]\ntype = \"uint\"\ndefault = 1\nfoo = 1",
+        "[smp]\ntype = \"uint\"\ndefault = 0\nconstraints = [\"range(1..)\"]",
+        "[kernel]\nsmp = 1",
+    ] {
+        assert!(Config::from_schema(schema).is_err(), "{}", schema);
+    }
+}
+
+#[test]
+fn integration_test_schema() {
+    let spec = std::fs::read_to_string("../example-configs/defconfig.toml").unwrap();
+    let config = Config::from_toml(&spec).unwrap();
+    let schema = config.dump(OutputFormat::Schema).unwrap();
+    let from_schema = Config::from_schema(&schema).unwrap();
+    assert!(from_schema.diff(&config).unwrap().is_empty());
+    for item in config.iter() {
+        let other = from_schema
+            .config_at(item.table_name(), item.key())
+            .unwrap();
+        assert_eq!(item.value().ty(), other.value().ty());
+    }
+}
+
+#[test]
+fn integration_test_json_schema() {
+    let validate = |spec: &str, toml: &str| {
+        let config = Config::from_toml(spec).unwrap();
+        let schema = config.dump(OutputFormat::JsonSchema).unwrap();
+        let schema = serde_json::from_str(&schema).unwrap();
+        let validator = jsonschema::validator_for(&schema).unwrap();
+        let toml = toml_edit::de::from_str::<serde_json::Value>(toml).unwrap();
+        let errors = validator.iter_errors(&toml).map(|e| e.to_string());
+        errors.collect::<Vec<_>>()
+    };
+
+    let spec = std::fs::read_to_string("../example-configs/defconfig.toml").unwrap();
+    let toml = std::fs::read_to_string("../example-configs/output.toml").unwrap();
+    assert!(validate(&spec, &toml).is_empty());
+
+    let spec = r#"
+    arch = "x86_64"                 # str
+    smp = 1                         # uint
+    [devices]
+    pci-ecam-base = 0xb000_0000     # uint
+    [__rules__]
+    smp = "smp <= 256"
+    [__depends_on__]
+    "devices.pci-ecam-base" = "arch == 'x86_64'"
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    let toml = config.dump(OutputFormat::Toml).unwrap();
+    assert!(toml.contains("[__rules__]") && toml.contains("[__depends_on__]"));
+    assert!(validate(spec, &toml).is_empty());
+    assert!(validate(spec, spec).is_empty());
+    assert!(validate(spec, "include = [\"common.toml\"]").is_empty());
+    assert_eq!(validate(spec, "smp = \"two\"").len(), 1);
+    assert_eq!(validate(spec, "cpus = 1").len(), 1);
+}
+
+#[test]
+fn test_json_schema() {
+    let spec = r#"
+    # Number of CPUs.
+    smp = 1                     # uint, range(1..=256)
+    offset = -16                # int, align(16)
+
+    # Kernel configs
+    [kernel]
+    # Name of the "kernel".
+    name = "arceos"             # str, regex("^[a-z]+$")
+    regions = [[0, "0x1000"]]   # [(uint, uint)]
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    assert_eq!(
+        config.dump(OutputFormat::JsonSchema).unwrap(),
+        r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "type": "object",
+  "properties": {
+    "offset": {
+      "type": ["integer", "string"],
+      "pattern": "^-?(0[xX][0-9a-fA-F_]+|0[bB][01_]+|0[oO][0-7_]+|[0-9_]+)$",
+      "multipleOf": 16,
+      "default": -16
+    },
+    "smp": {
+      "description": "Number of CPUs.",
+      "type": ["integer", "string"],
+      "pattern": "^(0[xX][0-9a-fA-F_]+|0[bB][01_]+|0[oO][0-7_]+|[0-9_]+)$",
+      "minimum": 1,
+      "maximum": 256,
+      "default": 1
+    },
+    "kernel": {
+      "description": "Kernel configs",
+      "type": "object",
+      "properties": {
+        "name": {
+          "description": "Name of the \"kernel\".",
+          "type": "string",
+          "pattern": "^[a-z]+$",
+          "default": "arceos"
+        },
+        "regions": {
+          "type": "array",
+          "items": {
+            "type": "array",
+            "prefixItems": [
+              {
+                "type": ["integer", "string"],
+                "pattern": "^(0[xX][0-9a-fA-F_]+|0[bB][01_]+|0[oO][0-7_]+|[0-9_]+)$",
+                "minimum": 0
+              },
+              {
+                "type": ["integer", "string"],
+                "pattern": "^(0[xX][0-9a-fA-F_]+|0[bB][01_]+|0[oO][0-7_]+|[0-9_]+)$",
+                "minimum": 0
+              }
+            ],
+            "minItems": 2,
+            "maxItems": 2
+          },
+          "default": [
+            [0, "0x1000"]
+          ]
+        }
+      },
+      "additionalProperties": false
+    },
+    "__rules__": {
+      "type": "object",
+      "additionalProperties": {
+        "type": "string"
+      }
+    },
+    "__depends_on__": {
+      "type": "object",
+      "additionalProperties": {
+        "type": "string"
+      }
+    },
+    "__types__": {
+      "type": "object",
+      "additionalProperties": {
+        "type": "string"
+      }
+    },
+    "include": {
+      "type": "array",
+      "items": {
+        "type": "string"
+      }
+    }
+  },
+  "additionalProperties": false
+}
+"#
+    );
+}
+
+#[test]
+fn test_typed_accessors() {
+    let spec = r#"
+    flag = true                                 # bool
+    offset = -1                                 # int
+    vaddr = "0xffff_ff80_0020_0000"             # uint
+    size = 0x800_0000                           # uint
+    name = "0x1000"                             # str
+    big = "0xffff_ffff_ffff_ffff"               # uint
+    regions = [["0xb000_0000", 0x1000]]         # [(uint, uint)]
+    pair = [1, "two"]                           # (int, str)
+    untyped = ["1", "0b11"]
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    let value = |key| {
+        config
+            .config_at(Config::GLOBAL_TABLE_NAME, key)
+            .unwrap()
+            .value()
+    };
+
+    assert_eq!(value("flag").as_bool(), Some(true));
+    assert_eq!(value("flag").as_u64(), None);
+    assert_eq!(value("offset").as_i64(), Some(-1));
+    assert_eq!(value("offset").as_u64(), None);
+    assert_eq!(value("vaddr").as_u64(), Some(0xffff_ff80_0020_0000));
+    assert_eq!(value("vaddr").as_i64(), None);
+    assert_eq!(value("vaddr").as_str(), None);
+    assert_eq!(value("size").as_i64(), Some(0x800_0000));
+    assert_eq!(value("name").as_str(), Some("0x1000"));
+    assert_eq!(value("name").as_u64(), None);
+    assert_eq!(value("big").as_i64(), None);
+    assert_eq!(value("big").as_u128(), Some(0xffff_ffff_ffff_ffff));
+    assert!(value("regions").as_tuple().is_none());
+    let regions = value("regions").as_array().unwrap();
+    let region = regions[0].as_tuple().unwrap();
+    assert_eq!(region[0].as_u64(), Some(0xb000_0000));
+    assert_eq!(region[0].ty(), Some(&ConfigType::Uint));
+    assert!(value("pair").as_array().is_none());
+    assert_eq!(value("pair").as_tuple().unwrap()[1].as_str(), Some("two"));
+    assert_eq!(value("untyped").as_array().unwrap()[1].as_u64(), Some(3));
+
+    assert!(config.get::<bool>("flag").unwrap());
+    assert_eq!(config.get::<i8>("offset").unwrap(), -1);
+    assert_eq!(config.get::<usize>("size").unwrap(), 0x800_0000);
+    assert_eq!(config.get::<String>("name").unwrap(), "0x1000");
+    assert_eq!(
+        config.get::<Vec<(u64, usize)>>("regions").unwrap(),
+        [(0xb000_0000, 0x1000)]
+    );
+    assert_eq!(
+        config.get::<(i32, String)>("pair").unwrap(),
+        (1, "two".into())
+    );
+    assert_eq!(config.get::<Vec<u8>>("untyped").unwrap(), [1, 3]);
+    assert!(config.get::<u32>("offset").is_err());
+    assert!(config.get::<u16>("size").is_err());
+    assert_err!(config.get::<u32>("flag"), ValueTypeMismatch);
+    assert_err!(config.get::<String>("vaddr"), ValueTypeMismatch);
+    assert_err!(config.get::<(u64, u64, u64)>("pair"), ValueTypeMismatch);
+    assert!(config.get::<bool>("kernel.flag").is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Arch {
+        X86_64,
+        Aarch64,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Platform<'a> {
+        family: &'a str,
+        phys_memory_size: usize,
+        #[serde(rename = "kernel-base-vaddr")]
+        vaddr: u64,
+        mmio_regions: Vec<(u64, u64)>,
+        name: String,
+        timer_irq: Option<u32>,
+        pci_ecam_base: Option<u64>,
+        rtc_base: Option<u64>,
+        cpu_ghz: f64,
+        timer_ratio: f32,
+        console_id: char,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct MyConfig<'a> {
+        arch: Arch,
+        smp: u8,
+        #[serde(borrow)]
+        platform: Platform<'a>,
+        devices: BTreeMap<String, Vec<u16>>,
+    }
+
+    let spec = r#"
+    arch = "x86_64"
+    smp = 4
+    [platform]
+    family = "x86-pc"
+    phys-memory-size = 0x800_0000                   # uint
+    kernel-base-vaddr = "0xffff_ff80_0020_0000"     # uint
+    mmio-regions = [["0xfec0_0000", "0x1000"]]      # [(uint, uint)]
+    name = "0x1000"                                 # str
+    timer-irq = "0xf0"
+    pci-ecam-base = "none"                          # uint?
+    rtc-base = "0x1000"                             # uint?
+    cpu-ghz = 2                                     # f64
+    timer-ratio = 0.5
+    console-id = "1"                                # char
+    [devices]
+    virtio-irqs = [1, "0x2"]
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    let cfg: MyConfig = config.deserialize().unwrap();
+    assert_eq!(cfg.arch, Arch::X86_64);
+    assert_eq!(cfg.smp, 4);
+    assert_eq!(cfg.platform.family, "x86-pc");
+    assert_eq!(cfg.platform.phys_memory_size, 0x800_0000);
+    assert_eq!(cfg.platform.vaddr, 0xffff_ff80_0020_0000);
+    assert_eq!(cfg.platform.mmio_regions, [(0xfec0_0000, 0x1000)]);
+    assert_eq!(cfg.platform.name, "0x1000");
+    assert_eq!(cfg.platform.timer_irq, Some(0xf0));
+    assert_eq!(cfg.platform.pci_ecam_base, None);
+    assert_eq!(cfg.platform.rtc_base, Some(0x1000));
+    assert_eq!(cfg.platform.cpu_ghz, 2.0);
+    assert_eq!(cfg.platform.timer_ratio, 0.5);
+    assert_eq!(cfg.platform.console_id, '1');
+    assert_eq!(cfg.devices["virtio-irqs"], [1, 2]);
+
+    let value = config
+        .config_at("platform", "mmio-regions")
+        .unwrap()
+        .value();
+    assert_eq!(
+        value.deserialize::<Vec<[u32; 2]>>().unwrap(),
+        [[0xfec0_0000, 0x1000]]
+    );
+    assert!(config
+        .config_at(Config::GLOBAL_TABLE_NAME, "smp")
+        .unwrap()
+        .value()
+        .deserialize::<bool>()
+        .is_err());
+    assert!(config.deserialize::<BTreeMap<String, u8>>().is_err());
+
+    // serialize back to TOML
+    let toml = toml_edit::ser::to_string_pretty(&config).unwrap();
+    let other = Config::from_toml(&toml).unwrap();
+    assert!(other.diff(&config).unwrap().is_empty());
+    assert!(config.diff(&other).unwrap().is_empty());
+    assert_eq!(
+        other
+            .config_at("platform", "kernel-base-vaddr")
+            .unwrap()
+            .value()
+            .to_toml_value(),
+        "\"0xffff_ff80_0020_0000\""
+    );
+}
+
+#[test]
+fn test_mutation() {
+    let mut config = Config::from_toml("smp = 1 # uint\n[kernel]\nstack-size = 0x1000").unwrap();
+
+    config
+        .add_table("platform", "Platform configs\n\nMultiple lines.")
+        .unwrap();
+    assert_eq!(
+        config.table_comments_at("platform"),
+        Some("# Platform configs\n#\n# Multiple lines.\n")
+    );
+    assert!(config.add_table("kernel", "").is_err());
+    assert!(config.add_table(Config::GLOBAL_TABLE_NAME, "").is_err());
+    assert!(config.add_table(Config::RULES_TABLE_NAME, "").is_err());
+    assert!(config.add_table(Config::TYPES_TABLE_NAME, "").is_err());
+
+    let value = ConfigValue::new("\"0xffff_ff80_0000_0000\"").unwrap();
+    config
+        .insert(
+            "platform",
+            "phys-virt-offset",
+            value.clone(),
+            Some(ConfigType::Uint),
+            "",
+        )
+        .unwrap();
+    let item = config.config_at("platform", "phys-virt-offset").unwrap();
+    assert_eq!(item.item_name(), "platform.phys-virt-offset");
+    assert_eq!(item.value().ty(), Some(&ConfigType::Uint));
+    assert!(config
+        .insert("platform", "phys-virt-offset", value.clone(), None, "")
+        .is_err());
+    assert!(config
+        .insert("devices", "base", value.clone(), None, "")
+        .is_err());
+    assert_err!(
+        config.insert("platform", "a", value.clone(), Some(ConfigType::Bool), ""),
+        ValueTypeMismatch
+    );
+    let typed = ConfigValue::new_with_type("1", "uint").unwrap();
+    let res = config.insert("platform", "b", typed, Some(ConfigType::Int), "");
+    assert_err!(res, ValueTypeMismatch);
+    config
+        .insert(
+            Config::GLOBAL_TABLE_NAME,
+            "arch",
+            ConfigValue::new("\"riscv64\"").unwrap(),
+            None,
+            "Architecture.",
+        )
+        .unwrap();
+
+    config
+        .rename("kernel", "stack-size", "task-stack-size")
+        .unwrap();
+    assert!(config.config_at("kernel", "stack-size").is_none());
+    assert_eq!(
+        config.get::<usize>("kernel.task-stack-size").unwrap(),
+        0x1000
+    );
+    assert!(config.rename("kernel", "stack-size", "foo").is_err());
+    assert!(config
+        .rename(Config::GLOBAL_TABLE_NAME, "smp", "arch")
+        .is_err());
+
+    assert_eq!(
+        config.dump_toml().unwrap(),
+        r#"# Architecture.
+arch = "riscv64"
+smp = 1 # uint
+
+[kernel]
+task-stack-size = 0x1000
+
+# Platform configs
+#
+# Multiple lines.
+[platform]
+phys-virt-offset = "0xffff_ff80_0000_0000" # uint
+"#
+    );
+
+    assert_eq!(
+        config
+            .remove(Config::GLOBAL_TABLE_NAME, "arch")
+            .unwrap()
+            .key(),
+        "arch"
+    );
+    assert!(config.remove(Config::GLOBAL_TABLE_NAME, "arch").is_err());
+    assert_eq!(config.remove_table("kernel").unwrap().len(), 1);
+    assert!(config.remove_table("kernel").is_err());
+    assert!(config.remove_table(Config::GLOBAL_TABLE_NAME).is_err());
+    assert!(config.table_comments_at("kernel").is_none());
+    config.add_rule("smp-limit", "smp <= 8").unwrap();
+    // items and tables referred to by rules or conditions are kept
+    let msg = config
+        .rename(Config::GLOBAL_TABLE_NAME, "smp", "nr-cpus")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(msg, "Config item `smp` is referred to by rule `smp-limit`");
+    assert!(config.remove(Config::GLOBAL_TABLE_NAME, "smp").is_err());
+    let mut cond = config.clone();
+    cond.add_dependency("platform", "smp > 1").unwrap();
+    let msg = cond.remove_table("platform").unwrap_err().to_string();
+    assert_eq!(
+        msg,
+        "Table `platform` is referred to by the condition of `platform`"
+    );
+    cond.add_table("dev", "").unwrap();
+    cond.add_rule("offset", "platform.phys-virt-offset > 0")
+        .unwrap();
+    assert!(cond.remove_table("platform").is_err());
+    assert!(cond
+        .remove("platform", "phys-virt-offset")
+        .unwrap_err()
+        .to_string()
+        .contains("rule `offset`"));
+    assert!(cond.remove_table("dev").is_ok());
+
+    let built = Config::builder()
+        .item("smp", "1", Some("uint"), "")
+        .table("platform", "Platform configs\n\nMultiple lines.")
+        .item(
+            "phys-virt-offset",
+            "\"0xffff_ff80_0000_0000\"",
+            Some("uint"),
+            "",
+        )
+        .rule("smp-limit", "smp <= 8")
+        .build()
+        .unwrap();
+    assert_eq!(built.dump_toml().unwrap(), config.dump_toml().unwrap());
+    assert_eq!(built.rules().count(), 1);
+
+    for builder in [
+        Config::builder().item("smp", "1", Some("bool"), ""),
+        Config::builder().item("smp", "1", Some("uint8"), ""),
+        Config::builder()
+            .item("smp", "1", None, "")
+            .item("smp", "2", None, ""),
+        Config::builder().table("kernel", "").table("kernel", ""),
+        Config::builder().rule("a", "1 +"),
+    ] {
+        assert!(builder.build().is_err());
+    }
+}
+
+#[test]
+fn test_renamed_from() {
+    let spec = r#"
+    smp = 1                     # uint, renamed_from("nr-cpus", "cpus")
+    [devices]
+    # Offset of the bus.
+    bus-offset = 0x1000         # uint, renamed_from("phys-bus-offset"), deprecated("use `dma-offset`")
+    dma-offset = 0              # uint, renamed_from("kernel.dma"), deprecated
+    "#;
+    let mut config = Config::from_toml(spec).unwrap();
+    let item = config.config_at("devices", "bus-offset").unwrap();
+    assert_eq!(item.renamed_from(), ["phys-bus-offset"]);
+    assert_eq!(item.deprecated(), Some("use `dma-offset`"));
+    let item = config.config_at("devices", "dma-offset").unwrap();
+    assert_eq!(item.renamed_from(), ["kernel.dma"]);
+    assert_eq!(item.deprecated(), Some(""));
+
+    // round trip
+    let toml = config.dump_toml().unwrap();
+    assert!(toml.contains(
+        r#"bus-offset = 0x1000 # uint, deprecated("use `dma-offset`"), renamed_from("phys-bus-offset")"#
+    ));
+    let other = Config::from_toml(&toml).unwrap();
+    assert_eq!(other.dump_toml().unwrap(), toml);
+    let schema = config.dump(OutputFormat::Schema).unwrap();
+    let other = Config::from_schema(&schema).unwrap();
+    assert_eq!(other.dump_toml().unwrap(), toml);
+
+    // migration
+    let oldconfig = r#"
+    cpus = 4
+    [devices]
+    phys-bus-offset = 0x2000
+    dma-offset = 8
+    [kernel]
+    dma = 4
+    "#;
+    let report = config
+        .update(&Config::from_toml(oldconfig).unwrap())
+        .unwrap();
+    assert_eq!(report.untouched().count(), 0);
+    // `devices.dma-offset` is set
+    assert_eq!(report.extra().collect::<Vec<_>>(), ["kernel.dma"]);
+    assert_eq!(
+        report.warnings()[0],
+        UpdateWarning::Migrated {
+            from: "cpus".into(),
+            to: "smp".into(),
+        }
+    );
+    assert_eq!(config.get::<usize>("smp").unwrap(), 4);
+    assert_eq!(config.get::<usize>("devices.bus-offset").unwrap(), 0x2000);
+    assert_eq!(config.get::<usize>("devices.dma-offset").unwrap(), 8);
+
+    let mut config = Config::from_toml(spec).unwrap();
+    let report = config
+        .overlay(&Config::from_toml("nr-cpus = 2\n[kernel]\ndma = 4").unwrap())
+        .unwrap();
+    assert_eq!(report.warnings().len(), 2);
+    assert_eq!(config.get::<usize>("smp").unwrap(), 2);
+    assert_eq!(config.get::<usize>("devices.dma-offset").unwrap(), 4);
+    assert!(config
+        .overlay(&Config::from_toml("[devices]\ndma = 4").unwrap())
+        .is_err());
+
+    let opts = DumpOptions {
+        deprecated_aliases: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        config.dump_with_options(OutputFormat::Rust, &opts).unwrap(),
+        r#"pub const SMP: usize = 2;
+#[deprecated(note = "renamed to `SMP`")]
+pub const NR_CPUS: usize = SMP;
+#[deprecated(note = "renamed to `SMP`")]
+pub const CPUS: usize = SMP;
+
+pub mod devices {
+    /// Offset of the bus.
+    #[deprecated(note = "use `dma-offset`")]
+    pub const BUS_OFFSET: usize = 0x1000;
+    #[deprecated(note = "renamed to `BUS_OFFSET`")]
+    #[allow(deprecated)]
+    pub const PHYS_BUS_OFFSET: usize = BUS_OFFSET;
+    #[deprecated]
+    pub const DMA_OFFSET: usize = 4;
+}
+"#
+    );
+    assert!(!config.dump_rs().unwrap().contains("deprecated"));
+
+    for spec in [
+        "a = 1 # uint, renamed_from()",
+        "a = 1 # uint, renamed_from(b)",
+        "a = 1 # uint, deprecated(1)",
+        "a = 1 # uint, deprecated(\"a\", \"b\")",
+    ] {
+        assert!(Config::from_toml(spec).is_err(), "{}", spec);
+    }
+}
+
+#[test]
+fn test_update_report() {
+    let spec = r#"
+    smp = 1 # uint, renamed_from("cpus")
+    name = "axvisor" # str
+    [kernel]
+    stack-size = 0x1000 # uint, range(0x1000..)
+    "#;
+    let oldconfig = r#"
+    cpus = "4" # str
+    name = 42
+    unknown = true
+    [kernel]
+    stack-size = "big"
+    "#;
+    let mut config = Config::from_toml(spec).unwrap();
+    let report = config
+        .update(&Config::from_toml(oldconfig).unwrap())
+        .unwrap();
+    assert_eq!(
+        report.warnings(),
+        [
+            UpdateWarning::Coerced {
+                item: "name".into(),
+                value: "42".into(),
+                from: "uint".into(),
+                to: "str".into(),
+            },
+            UpdateWarning::Mismatched {
+                item: "kernel.stack-size".into(),
+                value: "\"big\"".into(),
+                ty: "uint".into(),
+            },
+            UpdateWarning::Migrated {
+                from: "cpus".into(),
+                to: "smp".into(),
+            },
+            UpdateWarning::Coerced {
+                item: "smp".into(),
+                value: "\"4\"".into(),
+                from: "str".into(),
+                to: "uint".into(),
+            },
+            UpdateWarning::Extra {
+                item: "unknown".into(),
+            },
+        ]
+    );
+    assert_eq!(config.get::<usize>("smp").unwrap(), 4);
+    assert_eq!(config.get::<String>("name").unwrap(), "42");
+    assert_eq!(config.get::<usize>("kernel.stack-size").unwrap(), 0x1000);
+
+    assert_eq!(
+        report.to_string().lines().nth(1),
+        Some("config item `kernel.stack-size` = \"big\" does not match type `uint`, using default value")
+    );
+    let json = report.to_json();
+    assert!(json.starts_with("[\n  {\n    \"kind\": \"coerced\",\n    \"item\": \"name\","));
+    assert_eq!(json.matches("\"kind\"").count(), 5);
+    // reports of several updates can be collected into one
+    let mut all = UpdateReport::default();
+    all.extend(report.warnings().iter().cloned());
+    all.extend(report.warnings().iter().cloned());
+    assert_eq!(all.warnings().len(), 10);
+    assert_eq!(all.to_json().matches("\"kind\"").count(), 10);
+
+    // constraint violations are still errors
+    let res = config.update(&Config::from_toml("[kernel]\nstack-size = 0x10").unwrap());
+    assert!(res.is_err());
+    assert!(Config::new().update(&Config::new()).unwrap().is_empty());
+}
+
+#[test]
+fn test_strict_types() {
+    let spec = r#"
+    smp = 1 # uint
+    mode = "0o777"
+    [kernel]
+    regions = []
+    pair = [1, "a"]
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    let inferred = config
+        .inferred_items()
+        .unwrap()
+        .into_iter()
+        .map(|(item, ty)| (item.item_name(), ty.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        inferred,
+        [
+            ("mode".into(), "uint".into()),
+            ("kernel.pair".into(), "(uint, str)".into()),
+            ("kernel.regions".into(), "?".into()),
+        ]
+    );
+
+    // lenient mode: items of unknown types are skipped
+    assert!(!config.dump_rs().unwrap().contains("REGIONS"));
+    let strict = DumpOptions::default().strict(true);
+    let err = config
+        .dump_with_options(OutputFormat::Rust, &strict)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Config item `mode` has no type annotation (inferred as `uint`)"
+    );
+    assert_eq!(
+        config.check_strict().unwrap_err().to_string(),
+        err.to_string()
+    );
+
+    let config = Config::from_toml("mode = \"0o777\" # str\nregions = [] # [uint]").unwrap();
+    assert!(config.inferred_items().unwrap().is_empty());
+    assert!(config.check_strict().is_ok());
+    assert_eq!(
+        config
+            .dump_with_options(OutputFormat::Rust, &strict)
+            .unwrap(),
+        "pub const MODE: &str = \"0o777\";\npub const REGIONS: &[usize] = &[];\n"
+    );
+}
+
+#[test]
+fn test_inline_doc() {
+    let spec = r#"
+    # Number of CPUs.
+    smp = 1 # uint; at most 256
+    cpus = 2 # number of CPUs
+    mode = "a" # see below; or not
+    name = "b" #: str, regex("^[b;]+$"); the name
+    uint = "c" # ; uint
+    [kernel]
+    stack-size = 0x1000 #: uint
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    let doc = |name: &str| config.iter().find(|i| i.item_name() == name).unwrap().doc();
+    assert_eq!(doc("smp"), "at most 256");
+    assert_eq!(doc("cpus"), "number of CPUs");
+    assert_eq!(doc("mode"), "see below; or not");
+    assert_eq!(doc("name"), "the name");
+    assert_eq!(doc("uint"), "uint");
+    assert_eq!(doc("kernel.stack-size"), "");
+    let inferred = config.inferred_items().unwrap();
+    assert_eq!(inferred.len(), 3);
+
+    let toml = config.dump_toml().unwrap();
+    assert!(toml.contains("smp = 1 # uint; at most 256\n"));
+    assert!(toml.contains("cpus = 2 # number of CPUs\n"));
+    assert!(toml.contains("uint = \"c\" #; uint\n"));
+    let other = Config::from_toml(&toml).unwrap();
+    assert_eq!(other.dump_toml().unwrap(), toml);
+
+    let rust = config.dump_rs().unwrap();
+    assert!(rust.contains("/// Number of CPUs.\n/// at most 256\npub const SMP: usize = 1;\n"));
+    assert!(rust.contains("/// the name\npub const NAME: &str = \"b\";\n"));
+    let schema = config.dump(OutputFormat::Schema).unwrap();
+    assert!(schema.contains("description = \"\"\"\nNumber of CPUs.\nat most 256\"\"\"\n"));
+
+    for spec in [
+        "a = 1 #: number of CPUs",
+        "a = 1 # uint, range(1..; doc",
+        "a = 1 #: unit; doc",
+        "a = 1 # uint number of CPUs",
+    ] {
+        assert!(Config::from_toml(spec).is_err(), "{}", spec);
+    }
+    // mistyped types are not taken as documentation
+    assert_err!(Config::from_toml("a = 1 # unit"), InvalidType);
+    assert_err!(
+        Config::from_toml("a = [[1, 2]] # [(uint, uint]"),
+        InvalidType
+    );
+}
+
+#[test]
+fn test_rust_idents() {
+    let spec = r#"
+    fn = 1
+    2nd-uart = 2
+    "foo.bar" = 3
+    "größe" = 4
+    self = 5
+    [type]
+    self = 6
+    Mixed-Case = 7
+    [2nd]
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        r#"pub const _2ND_UART: usize = 2;
+pub const FN: usize = 1;
+pub const FOO_BAR: usize = 3;
+pub const GR__E: usize = 4;
+pub const SELF: usize = 5;
+
+pub mod _2nd {
+}
+
+pub mod r#type {
+    pub const MIXED_CASE: usize = 7;
+    pub const SELF: usize = 6;
+}
+"#
+    );
+    let opts = DumpOptions {
+        const_case: IdentCase::Lower,
+        mod_case: IdentCase::Upper,
+        ..Default::default()
+    };
+    assert_eq!(
+        config.dump_with_options(OutputFormat::Rust, &opts).unwrap(),
+        r#"pub const _2nd_uart: usize = 2;
+pub const r#fn: usize = 1;
+pub const foo_bar: usize = 3;
+pub const gr__e: usize = 4;
+pub const self_: usize = 5;
+
+pub mod _2ND {
+}
+
+pub mod TYPE {
+    pub const mixed_case: usize = 7;
+    pub const self_: usize = 6;
+}
+"#
+    );
+
+    // collisions
+    let check = |spec: &str, opts: &DumpOptions, msg: &str| {
+        let config = Config::from_toml(spec).unwrap();
+        let err = config
+            .dump_with_options(OutputFormat::Rust, opts)
+            .unwrap_err();
+        assert_eq!(err.to_string(), msg);
+        // other formats are not affected
+        config.dump_toml().unwrap();
+    };
+    let default = DumpOptions::default();
+    check(
+        "a-b = 1\na_b = 2",
+        &default,
+        "Rust identifier `A_B` of item `a_b` collides with item `a-b`",
+    );
+    check(
+        "[t]\nA = 1\na = 2",
+        &default,
+        "Rust identifier `A` of item `t.a` collides with item `t.A`",
+    );
+    check(
+        "[a-b]\n[a_b]",
+        &default,
+        "Rust identifier `a_b` of table `a_b` collides with table `a-b`",
+    );
+    check(
+        "[A]\n[a]",
+        &opts,
+        "Rust identifier `A` of table `a` collides with table `A`",
+    );
+    let aliases = DumpOptions {
+        deprecated_aliases: true,
+        ..Default::default()
+    };
+    check(
+        "a_b = 1\nc = 2 # uint, renamed_from(\"a-b\")",
+        &aliases,
+        "Rust identifier `A_B` of item `a-b` collides with item `a_b`",
+    );
+    // items in different tables do not collide
+    Config::from_toml("a = 1\n[t]\na = 2\n[u]\na = 3")
+        .unwrap()
+        .dump_rs()
+        .unwrap();
+
+    // keys and table names are quoted in the TOML output if needed
+    let toml = config.dump_toml().unwrap();
+    assert!(toml.contains("\n\"foo.bar\" = 3\n") && toml.contains("\n\"größe\" = 4\n"));
+    let spec = "\"a b\" = 1 # uint\n[\"dev.uart\"]\n\"ñ\" = 2 # uint\n";
+    let config = Config::from_toml(spec).unwrap();
+    let toml = config.dump_toml().unwrap();
+    assert!(toml.contains("[\"dev.uart\"]\n"));
+    let other = Config::from_toml(&toml).unwrap();
+    assert!(other.config_at("dev.uart", "ñ").is_some());
+    assert!(other.diff(&config).unwrap().is_empty());
+    assert_eq!(other.dump_toml().unwrap(), toml);
+}
+
+#[test]
+fn test_option() {
+    assert_eq!(
+        ConfigType::new("uint?").unwrap(),
+        ConfigType::new("option<uint>").unwrap()
+    );
+    let ty = ConfigType::new("option<(uint, str)>").unwrap();
+    assert_eq!(ty.to_string(), "(uint, str)?");
+    assert_eq!(ty.to_rust_type(), "Option<(usize, &str)>");
+    assert_eq!(
+        ConfigType::new("[uint?]").unwrap().to_rust_type(),
+        "&[Option<usize>]"
+    );
+    assert_err!(ConfigType::new("uint??"), InvalidType);
+    assert_err!(ConfigType::new("option<uint?>"), InvalidType);
+    assert_err!(ConfigType::new("option<>"), InvalidType);
+    // the string `"none"` could not be told from `None`
+    for ty in ["str?", "option<str>", "[str?]"] {
+        assert_eq!(
+            ConfigType::new(ty).unwrap_err().to_string(),
+            "Optional type `str?` is ambiguous, as the string \"none\" means `None`"
+        );
+    }
+    assert!(ConfigType::new("option<str as Name>").is_err());
+    assert!(Config::from_toml("name = \"a\" # str?").is_err());
+
+    let spec = r#"
+    pci-ecam-base = "none" # uint?, align(0x1000)
+    name = "a" # option<char>
+    regions = [1, "none"] # [uint?]
+    "#;
+    let mut config = Config::from_toml(spec).unwrap();
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        r#"pub const NAME: Option<char> = Some('a');
+pub const PCI_ECAM_BASE: Option<usize> = None;
+pub const REGIONS: &[Option<usize>] = &[Some(1), None];
+"#
+    );
+    assert!(config
+        .dump_toml()
+        .unwrap()
+        .contains("pci-ecam-base = \"none\" # uint?, align(0x1000)\n"));
+    let item = config.config_at("$GLOBAL", "pci-ecam-base").unwrap();
+    assert!(item.value().is_none());
+    assert_eq!(item.value().as_u64(), None);
+    assert_eq!(config.get::<Option<u64>>("pci-ecam-base").unwrap(), None);
+    assert_eq!(config.get::<Option<char>>("name").unwrap(), Some('a'));
+    assert_eq!(
+        config.get::<Vec<Option<usize>>>("regions").unwrap(),
+        [Some(1), None]
+    );
+
+    // set and clear
+    let report = config
+        .update(&Config::from_toml("pci-ecam-base = 0xb000_0000\nname = \"none\"").unwrap())
+        .unwrap();
+    assert_eq!(report.untouched().collect::<Vec<_>>(), ["regions"]);
+    assert_eq!(
+        config.get::<Option<u64>>("pci-ecam-base").unwrap(),
+        Some(0xb000_0000)
+    );
+    assert_eq!(config.get::<Option<char>>("name").unwrap(), None);
+    let item = config.config_at_mut("$GLOBAL", "pci-ecam-base").unwrap();
+    assert!(matches!(
+        item.value_mut().update(ConfigValue::new("0x1234").unwrap()),
+        Err(ConfigErr::ConstraintViolation { .. })
+    ));
+    item.value_mut().update(ConfigValue::none()).unwrap();
+    assert!(item.value().is_none());
+    let res = Config::from_toml("a = \"none\" # uint");
+    assert_err!(res, ValueTypeMismatch);
+
+    // expressions compare with the string `none`
+    config.add_rule("ecam", "pci-ecam-base == 'none'").unwrap();
+    config.check_rules().unwrap();
+}
+
+#[test]
+fn test_fixed_array() {
+    let ty = ConfigType::new("[uint; 0x4]").unwrap();
+    assert_eq!(
+        ty,
+        ConfigType::FixedArray(Box::new(ConfigType::Uint), ArrayLen::Fixed(4))
+    );
+    assert_eq!(ty.to_string(), "[uint; 4]");
+    assert_eq!(ty.to_rust_type(), "[usize; 4]");
+    let ty = ConfigType::new("[(uint, str); kernel.max-cpus]").unwrap();
+    assert_eq!(ty.to_string(), "[(uint, str); kernel.max-cpus]");
+    assert_eq!(ty.to_rust_type(), "[(usize, &str); MAX_CPUS]");
+    for ty in [
+        "[uint; ]",
+        "[uint; -1]",
+        "[uint; 1; 2]",
+        "[uint; 2cpus]",
+        "[; 1]",
+    ] {
+        assert_err!(ConfigType::new(ty), InvalidType);
+    }
+
+    assert!(ConfigValue::new_with_type("[1, 2]", "[uint; 2]").is_ok());
+    assert_err!(
+        ConfigValue::new_with_type("[1, 2]", "[uint; 3]"),
+        ValueTypeMismatch
+    );
+    assert_err!(
+        ConfigValue::new_with_type("[1, \"a\"]", "[uint; 2]"),
+        ValueTypeMismatch
+    );
+
+    let spec = r#"
+    smp = 2 # uint
+    stacks = [0x1000, 0x2000] # [uint; smp]
+    [kernel]
+    max-cpus = 2 # uint
+    irqs = [[1, 2], [3, 4]] # [[uint; 2]; max-cpus]
+    [percpu]
+    bases = ["0x1000", "0x2000"] # [uint; kernel.max-cpus]
+    ids = [0, 1] # [int; smp]
+    "#;
+    let mut config = Config::from_toml(spec).unwrap();
+    let res = config.check_rules();
+    assert_eq!(
+        res.unwrap_err().to_string(),
+        "Config item `kernel.irqs`: Array length `max-cpus` is not a `uint` item"
+    );
+    let item = config.config_at_mut("kernel", "irqs").unwrap();
+    *item = Config::from_toml("[kernel]\nirqs = [[1, 2], [3, 4]] # [[uint; 2]; kernel.max-cpus]")
+        .unwrap()
+        .config_at("kernel", "irqs")
+        .unwrap()
+        .clone();
+    config.check_rules().unwrap();
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        r#"pub const SMP: usize = 2;
+pub const STACKS: [usize; SMP] = [0x1000, 0x2000];
+
+pub mod kernel {
+    pub const IRQS: [[usize; 2]; MAX_CPUS] = [
+        [1, 2],
+        [3, 4],
+    ];
+    pub const MAX_CPUS: usize = 2;
+}
+
+pub mod percpu {
+    pub const BASES: [usize; super::kernel::MAX_CPUS] = [0x1000, 0x2000];
+    pub const IDS: [isize; super::SMP] = [0, 1];
+}
+"#
+    );
+
+    config
+        .update(&Config::from_toml("smp = 4").unwrap())
+        .unwrap();
+    assert_eq!(
+        config.check_rules().unwrap_err().to_string(),
+        "Config item `stacks`: Array `[0x1000, 0x2000]` has 2 elements, but `smp` = 4\n\
+         Config item `percpu.ids`: Array `[0, 1]` has 2 elements, but `smp` = 4"
+    );
+    // also checked on dumping the Rust code
+    assert_eq!(
+        config.dump_rs().unwrap_err().to_string(),
+        "Config item `stacks`: Array `[0x1000, 0x2000]` has 2 elements, but `smp` = 4"
+    );
+    config.dump_toml().unwrap();
+    let json = config.dump(OutputFormat::JsonSchema).unwrap();
+    assert!(json.contains("\"minItems\": 2,\n"));
+}
+
+#[test]
+fn test_float_char() {
+    let ty = ConfigType::new("(f32, f64, char)").unwrap();
+    assert_eq!(ty.to_string(), "(f32, f64, char)");
+    assert_eq!(ty.to_rust_type(), "(f32, f64, char)");
+    assert_eq!(
+        ConfigValue::new("1.5").unwrap().inferred_type().unwrap(),
+        ConfigType::F64
+    );
+    // integers are assignable to floats, but not to chars
+    assert!(ConfigValue::new("3")
+        .unwrap()
+        .type_matches(&ConfigType::F64));
+    assert!(!ConfigValue::new("1.5")
+        .unwrap()
+        .type_matches(&ConfigType::Uint));
+    assert!(!ConfigValue::new("3")
+        .unwrap()
+        .type_matches(&ConfigType::Char));
+    assert!(!ConfigValue::new("\"ab\"")
+        .unwrap()
+        .type_matches(&ConfigType::Char));
+    assert_err!(
+        ConfigValue::new_with_type("1e300", "f32"),
+        ValueTypeMismatch
+    );
+
+    let spec = r#"
+    ratio = 1.5e-3 # f32
+    freq = 3 # f64
+    max = inf # f64
+    min = -inf # f32
+    nan = nan # f64
+    sep = "/" # char
+    digit = "7" # char, regex("[0-9]")
+    quote = "'" # char
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        r#"pub const DIGIT: char = '7';
+pub const FREQ: f64 = 3.0;
+pub const MAX: f64 = f64::INFINITY;
+pub const MIN: f32 = f32::NEG_INFINITY;
+pub const NAN: f64 = f64::NAN;
+pub const QUOTE: char = '\'';
+pub const RATIO: f32 = 0.0015;
+pub const SEP: char = '/';
+"#
+    );
+    assert!(config
+        .dump_toml()
+        .unwrap()
+        .contains("ratio = 1.5e-3 # f32\n"));
+    assert_eq!(config.get::<f32>("ratio").unwrap(), 1.5e-3);
+    assert_eq!(config.get::<f64>("freq").unwrap(), 3.0);
+    assert!(config.get::<f64>("nan").unwrap().is_nan());
+    assert_eq!(config.get::<char>("digit").unwrap(), '7');
+    assert_eq!(config.get::<u64>("digit").ok(), None);
+
+    let a = ConfigValue::new("3").unwrap();
+    assert!(a.value_eq(&ConfigValue::new("3.0").unwrap()));
+    assert!(!a.value_eq(&ConfigValue::new("3.5").unwrap()));
+    let res = Config::from_toml("digit = \"a\" # char, regex(\"[0-9]\")");
+    assert!(matches!(res, Err(ConfigErr::ConstraintViolation { .. })));
+
+    // floats in rules, integers are promoted
+    let mut config = config;
+    for (name, rule) in [
+        ("freq", "freq > 1 && freq * 2 == 6 && freq / 2 == 1.5"),
+        ("ratio", "ratio < 0.01 && ratio > 1.5e-4 && -ratio < 0"),
+        ("max", "max(freq, 2.5, 1) == 3 && min(freq, 2.5) == 2.5"),
+        ("inf", "max > 1_000.0 && min < -1e3"),
+    ] {
+        config.add_rule(name, rule).unwrap();
+    }
+    config.check_rules().unwrap();
+    for rule in ["freq & 1 == 1", "nan > 1", "freq > \"1\""] {
+        let mut config = config.clone();
+        config.add_rule("bad", rule).unwrap();
+        assert!(config.check_rules().is_err(), "{}", rule);
+    }
+    for rule in ["1.5.5 > 0", "0x1.5 > 0", "1.e3 > 0"] {
+        assert!(config.clone().add_rule("bad", rule).is_err(), "{}", rule);
+    }
+}
+
+#[test]
+fn test_flags() {
+    let ty = ConfigType::new("flags(READ=1, WRITE = 0x2, EXEC=0b100)").unwrap();
+    assert_eq!(ty.to_string(), "flags(READ=0x1, WRITE=0x2, EXEC=0x4)");
+    assert_eq!(ty.to_rust_type(), "usize");
+    assert_eq!(ConfigType::new(&ty.to_string()).unwrap(), ty);
+    assert_err!(ConfigType::new("flags()"), InvalidType);
+    assert_err!(ConfigType::new("flags(READ=1, READ=2)"), InvalidType);
+    assert_err!(ConfigType::new("flags(1READ=1)"), InvalidType);
+    assert_err!(ConfigType::new("flags(READ=-1)"), InvalidType);
+    assert_err!(ConfigType::new("flags(READ)"), InvalidType);
+
+    let spec = r#"
+    mmio-attrs = ["READ", "WRITE"]  # flags(READ=0x1, WRITE=0x2, EXEC=0x4)
+    irq-mode = "0x3"                # flags(EDGE=0x1, HIGH=0x2)
+    none = []                       # flags(A=0x1)
+    "#;
+    let mut config = Config::from_toml(spec).unwrap();
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        "pub const IRQ_MODE: usize = 0x3;\n\
+         pub const MMIO_ATTRS: usize = 0x3;\n\
+         pub const NONE: usize = 0x0;\n"
+    );
+    let opts = DumpOptions {
+        flag_consts: true,
+        ..Default::default()
+    };
+    let rs = config.dump_with_options(OutputFormat::Rust, &opts).unwrap();
+    assert!(rs.contains(
+        "pub const MMIO_ATTRS: usize = 0x3;\n\
+         pub const MMIO_ATTRS_READ: usize = 0x1;\n\
+         pub const MMIO_ATTRS_WRITE: usize = 0x2;\n\
+         pub const MMIO_ATTRS_EXEC: usize = 0x4;\n"
+    ));
+    assert!(config
+        .dump_toml()
+        .unwrap()
+        .contains("mmio-attrs = [\"READ\", \"WRITE\"] # flags(READ=0x1, WRITE=0x2, EXEC=0x4)\n"));
+    assert_eq!(config.get::<u64>("mmio-attrs").unwrap(), 3);
+    assert_eq!(config.get::<u8>("irq-mode").unwrap(), 3);
+
+    // unknown names and bits are rejected
+    let res = Config::from_toml("a = [\"READ\", \"EXCE\"] # flags(READ=1, EXEC=4)");
+    assert_err!(res, ValueTypeMismatch);
+    let res = Config::from_toml("a = 0x8 # flags(READ=1, EXEC=4)");
+    assert_err!(res, ValueTypeMismatch);
+
+    // names and integers are interchangeable
+    let item = config.config_at_mut("$GLOBAL", "mmio-attrs").unwrap();
+    assert!(item
+        .value()
+        .value_eq(&ConfigValue::new("[\"WRITE\", \"READ\"]").unwrap()));
+    assert!(item.value().value_eq(&ConfigValue::new("3").unwrap()));
+    item.value_mut()
+        .update(ConfigValue::new("5").unwrap())
+        .unwrap();
+    config
+        .add_rule("no-wx", "(mmio-attrs & 0x6) != 0x6")
+        .unwrap();
+    config.check_rules().unwrap();
+
+    // flag constants collide with other items
+    let config = Config::from_toml("a = [] # flags(B=1)\na-b = 1").unwrap();
+    let err = config
+        .dump_with_options(OutputFormat::Rust, &opts)
+        .unwrap_err();
+    assert!(err.to_string().contains("collides"));
+}
+
+#[test]
+fn test_type_aliases() {
+    let spec = r#"
+    regions = [[0, 0x1000]]         # [region]
+    smp = 2                         # uint
+    [kernel]
+    stack = [0x8000, 0x4000]        # range, pow2
+    cpus = [[0, 1], [2, 3]]         # [cpu-set]
+
+    [__types__]
+    # Range with format (`start`, `size`).
+    range = "(uint, uint)"
+    region = "range"
+    cpu-set = "[uint; smp]"
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    assert_eq!(
+        config.type_alias_comments("range").map(str::trim),
+        Some("# Range with format (`start`, `size`).")
+    );
+    assert_eq!(config.type_alias_comments("region"), None);
+    let aliases = config
+        .type_aliases()
+        .map(|(name, ty)| format!("{} = {}", name, ty))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        aliases,
+        [
+            "cpu-set = [uint; smp]",
+            "range = (uint, uint)",
+            "region = range"
+        ]
+    );
+    let item = config.config_at("$GLOBAL", "regions").unwrap();
+    let ty = item.value().ty().unwrap();
+    assert_eq!(ty.to_string(), "[region]");
+    assert_eq!(ty.to_rust_type(), "&[Region]");
+    assert_eq!(
+        ConfigType::new("region").unwrap_err().to_string(),
+        ConfigErr::InvalidType.to_string()
+    );
+    assert_eq!(
+        config.get::<Vec<(u64, u64)>>("regions").unwrap(),
+        [(0, 0x1000)]
+    );
+
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        r#"pub type CpuSet = [usize; SMP];
+/// Range with format (`start`, `size`).
+pub type Range = (usize, usize);
+pub type Region = Range;
+
+pub const REGIONS: &[Region] = &[
+    (0, 0x1000),
+];
+pub const SMP: usize = 2;
+
+pub mod kernel {
+    pub const CPUS: &[super::CpuSet] = &[
+        [0, 1],
+        [2, 3],
+    ];
+    pub const STACK: super::Range = (0x8000, 0x4000);
+}
+"#
+    );
+    let toml = config.dump_toml().unwrap();
+    assert!(toml.contains("] # [region]\n"));
+    assert!(toml.ends_with(
+        "[__types__]\ncpu-set = \"[uint; smp]\"\n\
+         # Range with format (`start`, `size`).\nrange = \"(uint, uint)\"\nregion = \"range\"\n"
+    ));
+    let other = Config::from_toml(&toml).unwrap();
+    assert!(other.diff(&config).unwrap().is_empty());
+    assert_eq!(other.dump_toml().unwrap(), toml);
+    let schema = config.dump(OutputFormat::Schema).unwrap();
+    let other = Config::from_schema(&schema).unwrap();
+    assert_eq!(
+        other.config_at("kernel", "cpus").unwrap().value().ty(),
+        config.config_at("kernel", "cpus").unwrap().value().ty()
+    );
+
+    // values of the resolved types are not coerced
+    let coerced = |toml: &str| {
+        let other = Config::from_toml(toml).unwrap();
+        let mut updated = config.clone();
+        let report = updated.update(&other).unwrap();
+        assert_eq!(
+            updated.get::<Vec<(u64, u64)>>("regions").unwrap(),
+            [(0, 0x2000)]
+        );
+        let mut warnings = report.warnings().iter();
+        warnings.any(|w| matches!(w, UpdateWarning::Coerced { .. }))
+    };
+    assert!(!coerced("regions = [[0, 0x2000]] # [(uint, uint)]"));
+    assert!(coerced("regions = [[0, 0x2000]] # [(int, uint)]"));
+
+    // values and constraints apply through aliases
+    let mut config = config;
+    config.check_rules().unwrap();
+    let res = Config::from_toml("a = [1] # region\n[__types__]\nregion = \"(uint, uint)\"");
+    assert_err!(res, ValueTypeMismatch);
+    let res = Config::from_toml("a = [1, 3] # range\n[__types__]\nrange = \"(uint, uint?)\"");
+    assert!(res.is_ok());
+    let item = config.config_at_mut("kernel", "stack").unwrap();
+    assert!(matches!(
+        item.value_mut()
+            .update(ConfigValue::new("[0x8000, 0x3000]").unwrap()),
+        Err(ConfigErr::ConstraintViolation { .. })
+    ));
+
+    // invalid definitions
+    let err = |types: &str| {
+        let spec = format!("[__types__]\n{}", types);
+        Config::from_toml(&spec).unwrap_err().to_string()
+    };
+    assert_eq!(
+        err("a = \"[b]\"\nb = \"(uint, c)\"\nc = \"a?\""),
+        "Type alias `a` is recursive: a -> b -> c -> a"
+    );
+    assert_eq!(err("a = \"a\""), "Type alias `a` is recursive: a -> a");
+    assert_eq!(err("a = \"[b]\""), "Invalid type `[b]` of type alias `a`");
+    assert_eq!(err("uint = \"int\""), "Invalid type alias name `uint`");
+    assert_eq!(err("self = \"int\""), "Invalid type alias name `self`");
+    assert_eq!(err("crate = \"int\""), "Invalid type alias name `crate`");
+    assert_eq!(err("a = 1"), "`__types__.a` must be a string");
+    let mut config = Config::new();
+    config.add_type_alias("a", "uint").unwrap();
+    assert!(config.add_type_alias("a", "int").is_err());
+    config.add_type_alias("b", "[a]").unwrap();
+    assert!(config.add_type_alias("c", "d").is_err());
+    assert!(config.type_alias("c").is_none());
+}
+
+#[test]
+fn test_mapped_types() {
+    let ty = ConfigType::new("uint  as memory_addr::PhysAddr   via from_usize").unwrap();
+    assert_eq!(
+        ty.to_string(),
+        "uint as memory_addr::PhysAddr via from_usize"
+    );
+    assert_eq!(ty.to_rust_type(), "memory_addr::PhysAddr");
+    assert_eq!(ty.resolved(), &ConfigType::Uint);
+    assert_eq!(
+        ConfigType::new("[(uint as PhysAddr via from_usize, uint as u32)]")
+            .unwrap()
+            .to_rust_type(),
+        "&[(PhysAddr, u32)]"
+    );
+    assert_err!(ConfigType::new("uint as"), InvalidType);
+    assert_err!(ConfigType::new("uint as PhysAddr via"), InvalidType);
+    assert_err!(ConfigType::new("uint as PhysAddr via a::b"), InvalidType);
+    assert_err!(ConfigType::new("uint as Phys-Addr"), InvalidType);
+    assert_err!(ConfigType::new("uint as A as B"), InvalidType);
+
+    let spec = r#"
+    phys-memory-base = 0x4000_0000  # uint as memory_addr::PhysAddr via from_usize, align(0x1000)
+    kernel-base = "none"            # uint as VirtAddr via from_usize?
+    irq = 5                         # uint as u32
+    regions = [[0x1000, 0x2000]]    # [(uint as PhysAddr via from_usize, uint)]
+    cpu-ids = [0, 1]                # [uint as CpuId via new; 2]
+    "#;
+    let config = Config::from_toml(spec).unwrap();
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        r#"pub const CPU_IDS: [CpuId; 2] = [CpuId::new(0), CpuId::new(1)];
+pub const IRQ: u32 = 5;
+pub const KERNEL_BASE: Option<VirtAddr> = None;
+pub const PHYS_MEMORY_BASE: memory_addr::PhysAddr = memory_addr::PhysAddr::from_usize(0x4000_0000);
+pub const REGIONS: &[(PhysAddr, usize)] = &[
+    (PhysAddr::from_usize(0x1000), 0x2000),
+];
+"#
+    );
+    assert!(config.dump_toml().unwrap().contains(
+        "phys-memory-base = 0x4000_0000 # uint as memory_addr::PhysAddr via from_usize, align(0x1000)\n"
+    ));
+    assert_eq!(
+        config.get::<usize>("phys-memory-base").unwrap(),
+        0x4000_0000
+    );
+    assert_eq!(config.get::<Option<u64>>("kernel-base").unwrap(), None);
+
+    // values are checked against the config types
+    let res = Config::from_toml("a = \"x\" # uint as PhysAddr via from_usize");
+    assert_err!(res, ValueTypeMismatch);
+    let res = Config::from_toml("a = 0x1001 # uint as PhysAddr via from_usize, align(0x1000)");
+    assert!(matches!(res, Err(ConfigErr::ConstraintViolation { .. })));
+}
+
+#[test]
+fn test_variants() {
+    let spec = r#"
+    arch = "aarch64"                                                    # str
+    smp = 4                                                             # uint
+    [kernel]
+    ticks-per-sec = { default = 100, aarch64 = 1000, riscv64 = 500 }    # uint, by("arch")
+    task-stack-size = { default = 0x40000, 4 = 0x80000 }                # uint, align(0x1000), by("smp")
+    "#;
+    let mut config = Config::from_toml(spec).unwrap();
+    let ticks = config.config_at("kernel", "ticks-per-sec").unwrap();
+    assert_eq!(ticks.selector(), Some("arch"));
+    assert_eq!(ticks.variants().len(), 3);
+    assert_eq!(ticks.variants()[0].0, "default");
+    assert_eq!(ticks.value().as_u64(), Some(100));
+    // unresolved variants are kept in the output
+    assert!(config.dump_toml().unwrap().contains(
+        "ticks-per-sec = { default = 100, aarch64 = 1000, riscv64 = 500 } # uint, by(\"arch\")\n"
+    ));
+
+    config.set_source(ConfigSource::new("spec", None));
+    config.resolve_variants().unwrap();
+    assert_eq!(config.get::<u64>("kernel.ticks-per-sec").unwrap(), 1000);
+    assert_eq!(
+        config.get::<u64>("kernel.task-stack-size").unwrap(),
+        0x80000
+    );
+    let ticks = config.config_at("kernel", "ticks-per-sec").unwrap();
+    assert_eq!(ticks.variant(), Some("aarch64"));
+    assert_eq!(
+        ticks.history().last().unwrap().0,
+        ConfigSource::new("variant", Some("aarch64"))
+    );
+    assert_eq!(
+        config.dump_toml().unwrap(),
+        r#"arch = "aarch64" # str
+smp = 4 # uint
+
+[kernel]
+task-stack-size = 0x80000 # uint, align(0x1000)
+ticks-per-sec = 1000 # uint
+"#
+    );
+
+    // the schema keeps the variants
+    let schema = config.dump(OutputFormat::Schema).unwrap();
+    assert!(schema
+        .contains("default = 100\nby = \"arch\"\nvariants = { aarch64 = 1000, riscv64 = 500 }\n"));
+    let mut config = Config::from_schema(&schema).unwrap();
+    let arch = config
+        .config_at_mut(Config::GLOBAL_TABLE_NAME, "arch")
+        .unwrap();
+    arch.value_mut()
+        .update(ConfigValue::new("\"x86_64\"").unwrap())
+        .unwrap();
+    config.resolve_variants().unwrap();
+    assert_eq!(config.get::<u64>("kernel.ticks-per-sec").unwrap(), 100);
+    let ticks = config.config_at("kernel", "ticks-per-sec").unwrap();
+    assert_eq!(ticks.variant(), Some("default"));
+
+    // every variant is checked against the type and constraints
+    let res = Config::from_toml(r#"a = { default = 1, x = true } # uint, by("b")"#);
+    assert_err!(res, ValueTypeMismatch);
+    let res =
+        Config::from_toml(r#"a = { default = 0x1000, x = 1 } # uint, align(0x1000), by("b")"#);
+    assert!(matches!(res, Err(ConfigErr::ConstraintViolation { .. })));
+    assert!(Config::from_toml(r#"a = { x = 1 } # uint, by("b")"#).is_err());
+    assert!(Config::from_toml(r#"a = { default = 1 } # uint"#).is_err());
+    assert!(Config::from_toml(r#"a = 1 # uint, by("b")"#).is_err());
+    let mut config = Config::from_toml(r#"a = { default = 1 } # uint, by("b")"#).unwrap();
+    assert_eq!(
+        config.resolve_variants().unwrap_err().to_string(),
+        "Selector `b` of config item `a` not found"
+    );
+
+    // the selector may be changed by the other layers before resolving
+    let spec = Config::from_toml(&spec.replace("arch = \"aarch64\"", "arch = \"x86_64\"")).unwrap();
+    let mut config = spec.clone();
+    config
+        .update(&Config::from_toml("arch = \"riscv64\"").unwrap())
+        .unwrap();
+    config.resolve_variants().unwrap();
+    assert_eq!(config.get::<u64>("kernel.ticks-per-sec").unwrap(), 500);
+    assert!(config
+        .diff(&spec)
+        .unwrap()
+        .config_at("kernel", "ticks-per-sec")
+        .is_none());
+
+    let mut config = spec.clone();
+    let arch = config
+        .config_at_mut(Config::GLOBAL_TABLE_NAME, "arch")
+        .unwrap();
+    let value = ConfigValue::new("\"aarch64\"").unwrap();
+    arch.update_from(value, ConfigSource::new("command line", None))
+        .unwrap();
+    config.resolve_variants().unwrap();
+    assert_eq!(config.get::<u64>("kernel.ticks-per-sec").unwrap(), 1000);
+
+    // explicit values override the variants
+    let mut config = spec.clone();
+    let old = "arch = \"aarch64\"\n[kernel]\nticks-per-sec = 100";
+    config.update(&Config::from_toml(old).unwrap()).unwrap();
+    config.resolve_variants().unwrap();
+    let ticks = config.config_at("kernel", "ticks-per-sec").unwrap();
+    assert_eq!(ticks.value().as_u64(), Some(100));
+    assert_eq!(ticks.selector(), None);
+    assert_eq!(ticks.variant(), None);
+    let defconfig = config.diff(&spec).unwrap();
+    assert!(defconfig.config_at("kernel", "ticks-per-sec").is_some());
+    assert!(defconfig.config_at("kernel", "task-stack-size").is_none());
+}
+
+#[test]
+fn test_include() {
+    use std::path::Path;
+
+    let dir = std::env::temp_dir().join(format!("axconfig-include-{}", std::process::id()));
+    let write = |path: &str, content: &str| {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    write(
+        "common/types.toml",
+        "[__types__]\nregion = \"(uint, uint)\"\n",
+    );
+    write(
+        "common/kernel.toml",
+        "include = [\"types.toml\"]\n[kernel]\ntask-stack-size = 0x40000 # uint\n",
+    );
+    write(
+        "common/devices.toml",
+        "include = [\"types.toml\"]\n[devices]\nmmio-regions = [] # [region]\n",
+    );
+    write(
+        "plat/qemu.toml",
+        r#"include = ["../common/kernel.toml", "../common/devices.toml"]
+plat = "qemu" # str
+[kernel]
+ticks-per-sec = 100 # uint
+"#,
+    );
+
+    let mut read = Vec::new();
+    let config = Config::from_file_with(dir.join("plat/qemu.toml"), |path| {
+        read.push(path.strip_prefix(&dir).unwrap().to_path_buf())
+    })
+    .unwrap();
+    let expected = [
+        "plat/qemu.toml",
+        "plat/../common/kernel.toml",
+        "plat/../common/types.toml",
+        "plat/../common/devices.toml",
+    ];
+    assert_eq!(read, expected.map(Path::new));
+    assert_eq!(
+        config.dump_rs().unwrap(),
+        r#"pub type Region = (usize, usize);
+
+pub const PLAT: &str = "qemu";
+
+pub mod devices {
+    pub const MMIO_REGIONS: &[super::Region] = &[];
+}
+
+pub mod kernel {
+    pub const TASK_STACK_SIZE: usize = 0x40000;
+    pub const TICKS_PER_SEC: usize = 100;
+}
+"#
+    );
+    let res = Config::from_toml(&std::fs::read_to_string(dir.join("plat/qemu.toml")).unwrap());
+    assert!(res.is_err());
+
+    // include cycles
+    write("a.toml", "include = [\"b.toml\"]\n");
+    write("b.toml", "include = [\"a.toml\"]\n");
+    let err = Config::from_file(dir.join("a.toml"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("Include cycle: "), "{}", err);
+
+    // errors are reported with the include chain
+    write("c.toml", "include = [\"d.toml\"]\n");
+    write("d.toml", "include = [\"e.toml\"]\n");
+    write("e.toml", "x = 1 # str\n");
+    let res = Config::from_file(dir.join("c.toml"));
+    match res {
+        Err(ConfigErr::Included { chain, err }) => {
+            assert!(matches!(*err, ConfigErr::ValueTypeMismatch));
+            let chain = chain.iter().map(|p| Path::new(p).file_name().unwrap());
+            assert_eq!(chain.collect::<Vec<_>>(), ["e.toml", "d.toml", "c.toml"]);
+        }
+        _ => panic!("unexpected result: {:?}", res),
+    }
+    let err = Config::from_file(dir.join("e.toml")).unwrap_err();
+    assert!(err.to_string().ends_with("e.toml\")"), "{}", err);
+
+    // the including file overrides the included items
+    write(
+        "plat/custom.toml",
+        "include = [\"../common/kernel.toml\"]\n[kernel]\ntask-stack-size = 0x80000\n",
+    );
+    let config = Config::from_file(dir.join("plat/custom.toml")).unwrap();
+    let item = config.config_at("kernel", "task-stack-size").unwrap();
+    assert_eq!(item.value().to_toml_value(), "0x80000");
+    assert_eq!(item.value().ty(), Some(&ConfigType::Uint));
+    let sources = item.history().iter().map(|(source, _)| {
+        let path = Path::new(source.path().unwrap());
+        (source.layer(), path.file_name().unwrap().to_str().unwrap())
+    });
+    assert_eq!(
+        sources.collect::<Vec<_>>(),
+        [("file", "kernel.toml"), ("file", "custom.toml")]
+    );
+    write(
+        "plat/bad.toml",
+        "include = [\"../common/kernel.toml\"]\n[kernel]\ntask-stack-size = \"big\"\n",
+    );
+    let err = Config::from_file(dir.join("plat/bad.toml")).unwrap_err();
+    assert!(err.to_string().contains("kernel.toml"), "{}", err);
+
+    // duplicate keys in the included files are reported with both files
+    write("i.toml", "y = 1 # uint\n");
+    write("j.toml", "y = 2 # uint\n");
+    write("k.toml", "include = [\"i.toml\", \"j.toml\"]\n");
+    let err = Config::from_file(dir.join("k.toml"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("Duplicate key `y` (in "), "{}", err);
+    assert!(err.contains("i.toml") && err.contains("j.toml"), "{}", err);
+
+    // diamond includes: the override applies to the shared item
+    write("diamond/common.toml", "[kernel]\nstack = 1 # uint\n");
+    write("diamond/b.toml", "include = [\"common.toml\"]\n");
+    write(
+        "diamond/c.toml",
+        "include = [\"common.toml\"]\n[kernel]\nstack = 2\n",
+    );
+    for includes in ["\"b.toml\", \"c.toml\"", "\"c.toml\", \"b.toml\""] {
+        write("diamond/a.toml", &format!("include = [{}]\n", includes));
+        let config = Config::from_file(dir.join("diamond/a.toml")).unwrap();
+        let item = config.config_at("kernel", "stack").unwrap();
+        assert_eq!(item.value().to_toml_value(), "2");
+    }
+    write("diamond/d.toml", "[kernel]\nstack = 3\n");
+    write("diamond/a.toml", "include = [\"c.toml\", \"d.toml\"]\n");
+    let err = Config::from_file(dir.join("diamond/a.toml")).unwrap_err();
+    assert!(
+        err.to_string().contains("Duplicate key `kernel.stack`"),
+        "{}",
+        err
+    );
+
+    write("f.toml", "include = [\"b.toml\"]\nx = 1\n");
+    write("g.toml", "include = \"f.toml\"\n");
+    assert!(Config::from_file(dir.join("g.toml")).is_err());
+    write("h.toml", "include = [\"missing.toml\"]\n");
+    assert!(Config::from_file(dir.join("h.toml")).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
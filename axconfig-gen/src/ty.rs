@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+
+use crate::value::parse_int;
 use crate::{ConfigErr, ConfigResult};
 
 /// The supported types in the config file.
@@ -11,10 +14,34 @@ pub enum ConfigType {
     Uint,
     /// String type (`str`).
     String,
+    /// Single-precision floating-point type (`f32`).
+    F32,
+    /// Double-precision floating-point type (`f64`).
+    F64,
+    /// Character type (`char`), whose value is a string of one character.
+    Char,
+    /// Bit flags type with the names and values of the flags (e.g.,
+    /// `flags(READ=0x1, WRITE=0x2)`), whose value is an array of flag names
+    /// or an integer.
+    Flags(Vec<(String, u64)>),
     /// Tuple type (e.g., `(int, str)`).
     Tuple(Vec<ConfigType>),
     /// Array type (e.g., `[int]`).
     Array(Box<ConfigType>),
+    /// Fixed-size array type (e.g., `[int; 4]` or `[int; smp]`).
+    FixedArray(Box<ConfigType>, ArrayLen),
+    /// Optional type (e.g., `uint?` or `option<uint>`), whose value may be
+    /// the string `"none"`. Optional strings are not supported.
+    Option(Box<ConfigType>),
+    /// Named type alias (e.g., `region`) with the type it refers to, which is
+    /// defined in the `[__types__]` table (see
+    /// [`Config::TYPES_TABLE_NAME`](crate::Config::TYPES_TABLE_NAME)).
+    Alias(String, Box<ConfigType>),
+    /// Type mapped to a user-provided Rust type (e.g.,
+    /// `uint as memory_addr::PhysAddr via from_usize`), with the config type,
+    /// the path of the Rust type, and the name of the const constructor (if
+    /// any) that converts the value of the config type.
+    Mapped(Box<ConfigType>, String, Option<String>),
     /// Type is unknown.
     ///
     /// It is used for type inference.
@@ -24,6 +51,24 @@ pub enum ConfigType {
 impl ConfigType {
     /// Parses a type string into a [`ConfigType`].
     pub fn new(ty: &str) -> ConfigResult<Self> {
+        Self::parse(ty, &mut |_| None)
+    }
+
+    /// Parses a type string into a [`ConfigType`], where the names of type
+    /// aliases are resolved by `aliases` (e.g., `[region]`).
+    pub fn new_with_aliases(
+        ty: &str,
+        aliases: &BTreeMap<String, ConfigType>,
+    ) -> ConfigResult<Self> {
+        Self::parse(ty, &mut |name| aliases.get(name).cloned().map(Ok))
+    }
+
+    /// Parses a type string, where `alias` resolves the names that are not
+    /// built-in types, or returns `None` if the name is unknown.
+    fn parse(
+        ty: &str,
+        alias: &mut dyn FnMut(&str) -> Option<ConfigResult<ConfigType>>,
+    ) -> ConfigResult<Self> {
         let ty = ty.trim();
         #[cfg(test)]
         if ty == "?" {
@@ -34,7 +79,46 @@ impl ConfigType {
             "int" => Ok(Self::Int),
             "uint" => Ok(Self::Uint),
             "str" => Ok(Self::String),
+            "f32" => Ok(Self::F32),
+            "f64" => Ok(Self::F64),
+            "char" => Ok(Self::Char),
             _ => {
+                let option = ty
+                    .strip_suffix('?')
+                    .or_else(|| ty.strip_prefix("option<")?.strip_suffix('>'));
+                if let Some(inner) = option {
+                    return match Self::parse(inner, alias)? {
+                        // `Option<Option<T>>` is ambiguous with a single `"none"`
+                        inner if matches!(inner.resolved(), Self::Option(_)) => {
+                            Err(ConfigErr::InvalidType)
+                        }
+                        // so is `Option<&str>`, which cannot be `Some("none")`
+                        inner if matches!(inner.resolved(), Self::String) => {
+                            Err(ConfigErr::Other(format!(
+                                "Optional type `{}?` is ambiguous, as the string \"none\" means `None`",
+                                inner
+                            )))
+                        }
+                        inner => Ok(Self::Option(Box::new(inner))),
+                    };
+                }
+                let words = split_top_level(ty, ' ');
+                if let Some(pos) = words.iter().position(|w| *w == "as") {
+                    let inner = Self::parse(&words[..pos].join(" "), alias)?;
+                    let rest = words[pos + 1..].iter().filter(|w| !w.is_empty());
+                    let (path, ctor) = match rest.copied().collect::<Vec<_>>()[..] {
+                        [path] => (path, None),
+                        [path, "via", ctor] if is_ident(ctor) => (path, Some(ctor.into())),
+                        _ => return Err(ConfigErr::InvalidType),
+                    };
+                    if !path.trim_start_matches("::").split("::").all(is_ident) {
+                        return Err(ConfigErr::InvalidType);
+                    }
+                    return Ok(Self::Mapped(Box::new(inner), path.into(), ctor));
+                }
+                if let Some(flags) = ty.strip_prefix("flags(").and_then(|s| s.strip_suffix(')')) {
+                    return parse_flags(flags).map(Self::Flags);
+                }
                 if ty.starts_with("(") && ty.ends_with(")") {
                     let tuple = ty[1..ty.len() - 1].trim();
                     if tuple.is_empty() {
@@ -43,15 +127,23 @@ impl ConfigType {
                     let items = split_tuple_items(tuple).ok_or(ConfigErr::InvalidType)?;
                     let tuple_types = items
                         .into_iter()
-                        .map(Self::new)
+                        .map(|ty| Self::parse(ty, alias))
                         .collect::<ConfigResult<Vec<_>>>()?;
                     Ok(Self::Tuple(tuple_types))
                 } else if ty.starts_with('[') && ty.ends_with("]") {
-                    let element = ty[1..ty.len() - 1].trim();
+                    let parts = split_top_level(&ty[1..ty.len() - 1], ';');
+                    let element = parts[0].trim();
                     if element.is_empty() {
                         return Err(ConfigErr::InvalidType);
                     }
-                    Ok(Self::Array(Box::new(Self::new(element)?)))
+                    let element = Box::new(Self::parse(element, alias)?);
+                    match parts[1..] {
+                        [] => Ok(Self::Array(element)),
+                        [len] => Ok(Self::FixedArray(element, ArrayLen::new(len)?)),
+                        _ => Err(ConfigErr::InvalidType),
+                    }
+                } else if let Some(resolved) = alias(ty) {
+                    Ok(Self::Alias(ty.into(), Box::new(resolved?)))
                 } else {
                     Err(ConfigErr::InvalidType)
                 }
@@ -59,27 +151,229 @@ impl ConfigType {
         }
     }
 
+    /// Returns the type that the type alias refers to, or the config type of
+    /// the mapped type, i.e., the type that values are checked against.
+    /// Otherwise, returns `self`.
+    pub fn resolved(&self) -> &Self {
+        match self {
+            Self::Alias(_, ty) | Self::Mapped(ty, ..) => ty.resolved(),
+            ty => ty,
+        }
+    }
+
+    /// Returns whether the types are the same after resolving the type
+    /// aliases in both (e.g., `[region]` and `[(uint, uint)]` if `region` is
+    /// `(uint, uint)`).
+    pub(crate) fn same_as(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Alias(_, ty), other) | (other, Self::Alias(_, ty)) => ty.same_as(other),
+            (Self::Tuple(a), Self::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same_as(b))
+            }
+            (Self::Array(a), Self::Array(b)) | (Self::Option(a), Self::Option(b)) => a.same_as(b),
+            (Self::FixedArray(a, n), Self::FixedArray(b, m)) => n == m && a.same_as(b),
+            (Self::Mapped(a, path_a, via_a), Self::Mapped(b, path_b, via_b)) => {
+                path_a == path_b && via_a == via_b && a.same_as(b)
+            }
+            (a, b) => a == b,
+        }
+    }
+
     /// Converts the type into a Rust type string.
+    ///
+    /// The lengths of fixed-size arrays referring to other items are converted
+    /// into the constant names, and the type aliases into the type names, as
+    /// if they are in the same module.
     pub fn to_rust_type(&self) -> String {
+        let len = |len: &ArrayLen| match len {
+            ArrayLen::Fixed(n) => n.to_string(),
+            ArrayLen::Item(name) => {
+                let key = name.rsplit('.').next().unwrap();
+                key.to_uppercase().replace('-', "_")
+            }
+        };
+        self.to_rust_type_with(&len, &alias_rust_name)
+    }
+
+    /// Converts the type into a Rust type string, with the lengths of
+    /// fixed-size arrays converted by `len`, and the names of type aliases
+    /// converted by `alias`.
+    pub(crate) fn to_rust_type_with(
+        &self,
+        len: &dyn Fn(&ArrayLen) -> String,
+        alias: &dyn Fn(&str) -> String,
+    ) -> String {
         match self {
             Self::Bool => "bool".into(),
             Self::Int => "isize".into(),
             Self::Uint => "usize".into(),
             Self::String => "&str".into(),
+            Self::F32 => "f32".into(),
+            Self::F64 => "f64".into(),
+            Self::Char => "char".into(),
+            Self::Flags(_) => "usize".into(),
             Self::Tuple(items) => {
                 let items = items
                     .iter()
-                    .map(Self::to_rust_type)
+                    .map(|ty| ty.to_rust_type_with(len, alias))
                     .collect::<Vec<_>>()
                     .join(", ");
                 format!("({})", items)
             }
-            Self::Array(ty) => format!("&[{}]", ty.to_rust_type()),
+            Self::Array(ty) => format!("&[{}]", ty.to_rust_type_with(len, alias)),
+            Self::FixedArray(ty, n) => {
+                format!("[{}; {}]", ty.to_rust_type_with(len, alias), len(n))
+            }
+            Self::Option(ty) => format!("Option<{}>", ty.to_rust_type_with(len, alias)),
+            Self::Alias(name, _) => alias(name),
+            Self::Mapped(_, path, _) => path.clone(),
             _ => panic!("Unknown type"),
         }
     }
 }
 
+/// Converts the name of a type alias into the Rust type name in
+/// `UpperCamelCase` (e.g., `mmio-region` to `MmioRegion`).
+pub(crate) fn alias_rust_name(name: &str) -> String {
+    name.split(['-', '_'])
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect()
+}
+
+/// Resolves the definitions of type aliases (e.g., `region = "(uint, uint)"`),
+/// which may refer to each other regardless of their order.
+///
+/// Names of the built-in types cannot be redefined, and recursive definitions
+/// are rejected.
+pub(crate) fn resolve_aliases(
+    defs: &BTreeMap<String, String>,
+) -> ConfigResult<BTreeMap<String, ConfigType>> {
+    fn resolve(
+        name: &str,
+        defs: &BTreeMap<String, String>,
+        resolved: &mut BTreeMap<String, ConfigType>,
+        resolving: &mut Vec<String>,
+    ) -> ConfigResult<ConfigType> {
+        if let Some(ty) = resolved.get(name) {
+            return Ok(ty.clone());
+        }
+        if let Some(pos) = resolving.iter().position(|n| n == name) {
+            return Err(ConfigErr::Other(format!(
+                "Type alias `{}` is recursive: {} -> {}",
+                name,
+                resolving[pos..].join(" -> "),
+                name
+            )));
+        }
+        resolving.push(name.into());
+        let ty = ConfigType::parse(&defs[name], &mut |n| {
+            defs.contains_key(n)
+                .then(|| resolve(n, defs, resolved, resolving))
+        })
+        .map_err(|e| match e {
+            ConfigErr::InvalidType => ConfigErr::Other(format!(
+                "Invalid type `{}` of type alias `{}`",
+                defs[name], name
+            )),
+            e => e,
+        })?;
+        resolving.pop();
+        resolved.insert(name.into(), ty.clone());
+        Ok(ty)
+    }
+
+    let mut resolved = BTreeMap::new();
+    for name in defs.keys() {
+        let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+        // `self` would be `Self` in Rust, like the other path keywords
+        let is_keyword = matches!(alias_rust_name(name).as_str(), "Self" | "Super" | "Crate");
+        if !is_name
+            || is_keyword
+            || ConfigType::new(name).is_ok()
+            || name == "flags"
+            || name == "option"
+        {
+            return Err(ConfigErr::Other(format!(
+                "Invalid type alias name `{}`",
+                name
+            )));
+        }
+        resolve(name, defs, &mut resolved, &mut Vec::new())?;
+    }
+    Ok(resolved)
+}
+
+/// The length of a fixed-size array type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArrayLen {
+    /// A literal length (e.g., `[int; 4]`).
+    Fixed(usize),
+    /// The value of another `uint` item, with the format `key` or `table.key`
+    /// (e.g., `[int; smp]`).
+    Item(String),
+}
+
+impl ArrayLen {
+    fn new(len: &str) -> ConfigResult<Self> {
+        let len = len.trim();
+        let is_name = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+        if let Some(n) = parse_int(len) {
+            n.try_into()
+                .map(Self::Fixed)
+                .map_err(|_| ConfigErr::InvalidType)
+        } else if !len.is_empty()
+            && !len.starts_with(|c: char| c.is_ascii_digit())
+            && len.chars().all(is_name)
+        {
+            Ok(Self::Item(len.into()))
+        } else {
+            Err(ConfigErr::InvalidType)
+        }
+    }
+}
+
+impl std::fmt::Display for ArrayLen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fixed(n) => write!(f, "{}", n),
+            Self::Item(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parses the flags in the format `NAME=value, ...`, where the names must be
+/// identifiers and unique.
+fn parse_flags(s: &str) -> ConfigResult<Vec<(String, u64)>> {
+    let mut flags = Vec::<(String, u64)>::new();
+    for flag in s.split(',') {
+        let (name, value) = flag.split_once('=').ok_or(ConfigErr::InvalidType)?;
+        let name = name.trim();
+        if !is_ident(name) || flags.iter().any(|(n, _)| n == name) {
+            return Err(ConfigErr::InvalidType);
+        }
+        let value = parse_int(value.trim())
+            .and_then(|v| u64::try_from(v).ok())
+            .ok_or(ConfigErr::InvalidType)?;
+        flags.push((name.into(), value));
+    }
+    Ok(flags)
+}
+
 fn split_tuple_items(s: &str) -> Option<Vec<&str>> {
     let mut items = Vec::new();
     let mut start = 0;
@@ -110,6 +404,38 @@ fn split_tuple_items(s: &str) -> Option<Vec<&str>> {
     }
 }
 
+/// Splits the string at the separators that are not enclosed in brackets or
+/// quotes.
+pub(crate) fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut level = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if let Some(q) = quote {
+            match c {
+                '\\' if q == '"' && !escaped => escaped = true,
+                _ if c == q && !escaped => quote = None,
+                _ => escaped = false,
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' => level += 1,
+            ')' | ']' => level -= 1,
+            _ if c == sep && level == 0 => {
+                items.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    items.push(&s[start..]);
+    items
+}
+
 impl std::fmt::Display for ConfigType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -117,6 +443,19 @@ impl std::fmt::Display for ConfigType {
             Self::Int => write!(f, "int"),
             Self::Uint => write!(f, "uint"),
             Self::String => write!(f, "str"),
+            Self::F32 => write!(f, "f32"),
+            Self::F64 => write!(f, "f64"),
+            Self::Char => write!(f, "char"),
+            Self::Flags(flags) => {
+                write!(f, "flags(")?;
+                for (i, (name, value)) in flags.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}={:#x}", name, value)?;
+                }
+                write!(f, ")")
+            }
             Self::Tuple(items) => {
                 write!(f, "(")?;
                 for (i, item) in items.iter().enumerate() {
@@ -128,6 +467,11 @@ impl std::fmt::Display for ConfigType {
                 write!(f, ")")
             }
             Self::Array(ty) => write!(f, "[{}]", ty),
+            Self::FixedArray(ty, len) => write!(f, "[{}; {}]", ty, len),
+            Self::Option(ty) => write!(f, "{}?", ty),
+            Self::Alias(name, _) => write!(f, "{}", name),
+            Self::Mapped(ty, path, None) => write!(f, "{} as {}", ty, path),
+            Self::Mapped(ty, path, Some(ctor)) => write!(f, "{} as {} via {}", ty, path, ctor),
             Self::Unknown => write!(f, "?"),
         }
     }
@@ -2,13 +2,17 @@ use std::fmt;
 
 use toml_edit::Value;
 
-use crate::{ConfigErr, ConfigResult, ConfigType};
+use crate::{ArrayLen, ConfigErr, ConfigResult, ConfigType, Constraint};
+
+/// The value of optional types that represents the absence of a value.
+const NONE: &str = "none";
 
 /// A structure representing a config value.
 #[derive(Clone)]
 pub struct ConfigValue {
     value: Value,
     ty: Option<ConfigType>,
+    constraints: Vec<Constraint>,
 }
 
 impl ConfigValue {
@@ -25,6 +29,16 @@ impl ConfigValue {
         Self::from_raw_value_type(&value, ty)
     }
 
+    /// Returns the value `"none"`, which represents the absence of a value
+    /// of optional types (e.g., `uint?`).
+    pub fn none() -> Self {
+        Self {
+            value: Value::from(NONE),
+            ty: None,
+            constraints: Vec::new(),
+        }
+    }
+
     pub(crate) fn from_raw_value(value: &Value) -> ConfigResult<Self> {
         if !value_is_valid(value) {
             return Err(ConfigErr::InvalidValue);
@@ -32,6 +46,7 @@ impl ConfigValue {
         Ok(Self {
             value: value.clone(),
             ty: None,
+            constraints: Vec::new(),
         })
     }
 
@@ -43,42 +58,120 @@ impl ConfigValue {
             Ok(Self {
                 value: value.clone(),
                 ty: Some(ty),
+                constraints: Vec::new(),
             })
         } else {
             Err(ConfigErr::ValueTypeMismatch)
         }
     }
 
+    pub(crate) fn raw(&self) -> &Value {
+        &self.value
+    }
+
     /// Returns the type of the config value if it is specified on construction.
     pub fn ty(&self) -> Option<&ConfigType> {
         self.ty.as_ref()
     }
 
+    /// Specifies the type of the config value.
+    ///
+    /// The value must match the type, and the type must be the same as the
+    /// current one if any.
+    pub(crate) fn set_type(&mut self, ty: ConfigType) -> ConfigResult<()> {
+        match &self.ty {
+            Some(cur) if *cur != ty => Err(ConfigErr::ValueTypeMismatch),
+            _ if !value_type_matches(&self.value, &ty) => Err(ConfigErr::ValueTypeMismatch),
+            _ => {
+                self.ty = Some(ty);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the constraints on the config value.
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
+    /// Sets the constraints on the config value.
+    ///
+    /// The value must have a type that all the constraints apply to, and it
+    /// must satisfy all the constraints. The constraints are also checked on
+    /// every [`update`](Self::update).
+    pub fn set_constraints(&mut self, constraints: Vec<Constraint>) -> ConfigResult<()> {
+        let ty = self.ty.as_ref();
+        for c in &constraints {
+            match ty {
+                Some(ty) if c.applies_to(ty) => c.check(&self.value, ty)?,
+                _ => {
+                    return Err(ConfigErr::Other(format!(
+                        "Constraint `{}` cannot be applied to type `{}`",
+                        c,
+                        ty.map_or("?".into(), |ty| ty.to_string()),
+                    )))
+                }
+            }
+        }
+        self.constraints = constraints;
+        Ok(())
+    }
+
     /// Updates the config value with a new value.
+    ///
+    /// The new value must match the type of the current value (if any), and
+    /// satisfy the constraints of both values.
     pub fn update(&mut self, new_value: Self) -> ConfigResult<()> {
         match (&self.ty, &new_value.ty) {
-            (Some(ty), Some(new_ty)) => {
-                if ty != new_ty {
-                    return Err(ConfigErr::ValueTypeMismatch);
-                }
+            (Some(ty), Some(new_ty)) if !ty.same_as(new_ty) => {
+                return Err(ConfigErr::ValueTypeMismatch);
             }
-            (Some(ty), None) => {
-                if !value_type_matches(&new_value.value, ty) {
-                    return Err(ConfigErr::ValueTypeMismatch);
-                }
+            (Some(ty), None) if !value_type_matches(&new_value.value, ty) => {
+                return Err(ConfigErr::ValueTypeMismatch);
             }
-            (None, Some(new_ty)) => {
-                if !value_type_matches(&self.value, new_ty) {
-                    return Err(ConfigErr::ValueTypeMismatch);
-                }
-                self.ty = new_value.ty;
+            (None, Some(new_ty)) if !value_type_matches(&self.value, new_ty) => {
+                return Err(ConfigErr::ValueTypeMismatch);
             }
             _ => {}
         }
+        if let Some(ty) = self.ty.as_ref().or(new_value.ty.as_ref()) {
+            for c in self.constraints.iter().chain(&new_value.constraints) {
+                c.check(&new_value.value, ty)?;
+            }
+        }
+        if self.ty.is_none() {
+            self.ty = new_value.ty;
+        }
+        if self.constraints.is_empty() {
+            self.constraints = new_value.constraints;
+        }
         self.value = new_value.value;
         Ok(())
     }
 
+    /// Returns the candidate values the config value can be coerced into, to
+    /// match the specified type.
+    ///
+    /// The candidates are the untyped value, and the string of the value if
+    /// the type is `str`.
+    pub(crate) fn coercions(&self, ty: Option<&ConfigType>) -> Vec<ConfigValue> {
+        let mut candidates = vec![Self {
+            value: self.value.clone(),
+            ty: None,
+            constraints: Vec::new(),
+        }];
+        if let (Some(ConfigType::String), Value::Integer(_) | Value::Float(_) | Value::Boolean(_)) =
+            (ty.map(ConfigType::resolved), &self.value)
+        {
+            candidates.push(Self {
+                value: Value::from(to_toml(&self.value)),
+                ty: None,
+                constraints: Vec::new(),
+            });
+        }
+        candidates
+    }
+
     /// Returns the inferred type of the config value.
     pub fn inferred_type(&self) -> ConfigResult<ConfigType> {
         inferred_type(&self.value)
@@ -89,6 +182,137 @@ impl ConfigValue {
         value_type_matches(&self.value, ty)
     }
 
+    /// Returns whether the config value is semantically equal to another one.
+    ///
+    /// Numbers are compared by their values regardless of their representation
+    /// (e.g., `0x10`, `"0x10"` and `16` are equal), unless the value is typed
+    /// as `str`.
+    pub fn value_eq(&self, other: &Self) -> bool {
+        value_eq(
+            &self.value,
+            &other.value,
+            self.ty.as_ref().or(other.ty.as_ref()),
+        )
+    }
+
+    /// Returns the boolean if the config value is a boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        self.value.as_bool()
+    }
+
+    /// Returns the integer if the config value is an integer in the range of
+    /// `i64`.
+    ///
+    /// Integers written as strings (e.g., `"0xffff_ff80_0000_0000"`) are also
+    /// parsed, unless the value is typed as `str` or `char`. Values of flags
+    /// types are combined into integers.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_int().and_then(|v| v.try_into().ok())
+    }
+
+    /// Returns the integer if the config value is an integer in the range of
+    /// `u64`. See [`as_i64`](Self::as_i64) for how integers are parsed.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_int().and_then(|v| v.try_into().ok())
+    }
+
+    /// Returns the integer if the config value is a non-negative integer. See
+    /// [`as_i64`](Self::as_i64) for how integers are parsed.
+    pub fn as_u128(&self) -> Option<u128> {
+        self.as_int().and_then(|v| v.try_into().ok())
+    }
+
+    /// Returns the floating-point number if the config value is a float or an
+    /// integer. See [`as_i64`](Self::as_i64) for how integers are parsed.
+    pub fn as_f64(&self) -> Option<f64> {
+        match &self.value {
+            _ if self.is_none() => None,
+            Value::Float(f) => Some(*f.value()),
+            _ => self.as_int().map(|v| v as f64),
+        }
+    }
+
+    /// Returns the character if the config value is a string of one
+    /// character, which is not typed as an integer.
+    pub fn as_char(&self) -> Option<char> {
+        let mut chars = self.as_str()?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the config value is `"none"` of an optional type. The
+    /// typed accessors return `None` for such values.
+    pub fn is_none(&self) -> bool {
+        let ty = self.ty.as_ref().map(ConfigType::resolved);
+        matches!(ty, Some(ConfigType::Option(_))) && is_none_value(&self.value)
+    }
+
+    /// Returns the type of the value, i.e., the inner type of optional types,
+    /// with type aliases resolved.
+    fn value_ty(&self) -> Option<&ConfigType> {
+        match self.ty.as_ref().map(ConfigType::resolved) {
+            Some(ConfigType::Option(ty)) => Some(ty.resolved()),
+            ty => ty,
+        }
+    }
+
+    /// Returns the string if the config value is a string, which is not typed
+    /// as an integer.
+    pub fn as_str(&self) -> Option<&str> {
+        match self.value_ty() {
+            _ if self.is_none() => None,
+            Some(ConfigType::Int | ConfigType::Uint) => None,
+            _ => self.value.as_str(),
+        }
+    }
+
+    /// Returns the elements if the config value is an array (typed as `[T]`
+    /// or untyped). The elements have the element type of the array.
+    pub fn as_array(&self) -> Option<Vec<ConfigValue>> {
+        let elem_ty = match self.value_ty() {
+            Some(ConfigType::Array(ty) | ConfigType::FixedArray(ty, _)) => Some(ty.as_ref()),
+            None => None,
+            _ => return None,
+        };
+        let arr = self.value.as_array()?;
+        Some(arr.iter().map(|v| Self::from_elem(v, elem_ty)).collect())
+    }
+
+    /// Returns the elements if the config value is a tuple (typed as
+    /// `(T1, T2, ...)` or untyped). The elements have the corresponding types
+    /// of the tuple.
+    pub fn as_tuple(&self) -> Option<Vec<ConfigValue>> {
+        let types = match self.value_ty() {
+            Some(ConfigType::Tuple(types)) => Some(types),
+            None => None,
+            _ => return None,
+        };
+        let arr = self.value.as_array()?;
+        let elems = arr.iter().enumerate().map(|(i, v)| {
+            let ty = types.and_then(|types| types.get(i));
+            Self::from_elem(v, ty)
+        });
+        Some(elems.collect())
+    }
+
+    fn as_int(&self) -> Option<i128> {
+        match self.value_ty() {
+            Some(ConfigType::String | ConfigType::Char) => None,
+            Some(ConfigType::Flags(flags)) => flags_value(&self.value, flags).map(Into::into),
+            _ => value_to_int(&self.value),
+        }
+    }
+
+    fn from_elem(value: &Value, ty: Option<&ConfigType>) -> Self {
+        Self {
+            value: value.clone(),
+            ty: ty.cloned(),
+            constraints: Vec::new(),
+        }
+    }
+
     /// Returns the TOML-formatted string of the config value.
     pub fn to_toml_value(&self) -> String {
         to_toml(&self.value)
@@ -107,10 +331,134 @@ impl fmt::Debug for ConfigValue {
         f.debug_struct("ConfigValue")
             .field("value", &self.to_toml_value())
             .field("type", &self.ty)
+            .field("constraints", &self.constraints)
             .finish()
     }
 }
 
+macro_rules! impl_try_from_int {
+    ($($t:ty),*) => {$(
+        impl TryFrom<&ConfigValue> for $t {
+            type Error = ConfigErr;
+
+            fn try_from(value: &ConfigValue) -> ConfigResult<Self> {
+                let v = value.as_int().ok_or(ConfigErr::ValueTypeMismatch)?;
+                v.try_into().map_err(|_| {
+                    ConfigErr::Other(format!(
+                        "Config value `{}` is out of range of `{}`",
+                        value.to_toml_value(),
+                        stringify!($t),
+                    ))
+                })
+            }
+        }
+    )*};
+}
+
+impl_try_from_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl TryFrom<&ConfigValue> for bool {
+    type Error = ConfigErr;
+
+    fn try_from(value: &ConfigValue) -> ConfigResult<Self> {
+        value.as_bool().ok_or(ConfigErr::ValueTypeMismatch)
+    }
+}
+
+impl TryFrom<&ConfigValue> for f64 {
+    type Error = ConfigErr;
+
+    fn try_from(value: &ConfigValue) -> ConfigResult<Self> {
+        value.as_f64().ok_or(ConfigErr::ValueTypeMismatch)
+    }
+}
+
+impl TryFrom<&ConfigValue> for f32 {
+    type Error = ConfigErr;
+
+    fn try_from(value: &ConfigValue) -> ConfigResult<Self> {
+        value
+            .as_f64()
+            .map(|v| v as f32)
+            .ok_or(ConfigErr::ValueTypeMismatch)
+    }
+}
+
+impl TryFrom<&ConfigValue> for char {
+    type Error = ConfigErr;
+
+    fn try_from(value: &ConfigValue) -> ConfigResult<Self> {
+        value.as_char().ok_or(ConfigErr::ValueTypeMismatch)
+    }
+}
+
+impl TryFrom<&ConfigValue> for String {
+    type Error = ConfigErr;
+
+    fn try_from(value: &ConfigValue) -> ConfigResult<Self> {
+        value
+            .as_str()
+            .map(Into::into)
+            .ok_or(ConfigErr::ValueTypeMismatch)
+    }
+}
+
+impl<T> TryFrom<&ConfigValue> for Option<T>
+where
+    T: for<'a> TryFrom<&'a ConfigValue, Error = ConfigErr>,
+{
+    type Error = ConfigErr;
+
+    fn try_from(value: &ConfigValue) -> ConfigResult<Self> {
+        if value.is_none() {
+            Ok(None)
+        } else {
+            T::try_from(value).map(Some)
+        }
+    }
+}
+
+impl<T> TryFrom<&ConfigValue> for Vec<T>
+where
+    T: for<'a> TryFrom<&'a ConfigValue, Error = ConfigErr>,
+{
+    type Error = ConfigErr;
+
+    fn try_from(value: &ConfigValue) -> ConfigResult<Self> {
+        let elems = value.as_array().ok_or(ConfigErr::ValueTypeMismatch)?;
+        elems.iter().map(T::try_from).collect()
+    }
+}
+
+macro_rules! impl_try_from_tuple {
+    ($($len:literal => ($($t:ident),+)),*) => {$(
+        impl<$($t),+> TryFrom<&ConfigValue> for ($($t,)+)
+        where
+            $($t: for<'a> TryFrom<&'a ConfigValue, Error = ConfigErr>,)+
+        {
+            type Error = ConfigErr;
+
+            fn try_from(value: &ConfigValue) -> ConfigResult<Self> {
+                let elems = value.as_tuple().ok_or(ConfigErr::ValueTypeMismatch)?;
+                if elems.len() != $len {
+                    return Err(ConfigErr::ValueTypeMismatch);
+                }
+                let mut elems = elems.iter();
+                Ok(($($t::try_from(elems.next().unwrap())?,)+))
+            }
+        }
+    )*};
+}
+
+impl_try_from_tuple! {
+    1 => (A),
+    2 => (A, B),
+    3 => (A, B, C),
+    4 => (A, B, C, D),
+    5 => (A, B, C, D, E),
+    6 => (A, B, C, D, E, F)
+}
+
 fn is_num(s: &str) -> bool {
     let s = s.to_lowercase().replace('_', "");
     if let Some(s) = s.strip_prefix("0x") {
@@ -124,9 +472,43 @@ fn is_num(s: &str) -> bool {
     }
 }
 
+pub(crate) fn parse_int(s: &str) -> Option<i128> {
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let s = s.to_lowercase().replace('_', "");
+    // at most one sign, which has been stripped
+    let parse = |s: &str, radix| {
+        if s.starts_with(['+', '-']) {
+            None
+        } else {
+            i128::from_str_radix(s, radix).ok()
+        }
+    };
+    let v = if let Some(s) = s.strip_prefix("0x") {
+        parse(s, 16)
+    } else if let Some(s) = s.strip_prefix("0b") {
+        parse(s, 2)
+    } else if let Some(s) = s.strip_prefix("0o") {
+        parse(s, 8)
+    } else {
+        parse(&s, 10)
+    }?;
+    Some(if neg { -v } else { v })
+}
+
+pub(crate) fn value_to_int(value: &Value) -> Option<i128> {
+    match value {
+        Value::Integer(i) => Some(*i.value() as i128),
+        Value::String(s) => parse_int(s.value()),
+        _ => None,
+    }
+}
+
 fn value_is_valid(value: &Value) -> bool {
     match value {
-        Value::Boolean(_) | Value::Integer(_) | Value::String(_) => true,
+        Value::Boolean(_) | Value::Integer(_) | Value::Float(_) | Value::String(_) => true,
         Value::Array(arr) => {
             for e in arr {
                 if !value_is_valid(e) {
@@ -139,10 +521,77 @@ fn value_is_valid(value: &Value) -> bool {
     }
 }
 
+/// Checks the lengths of fixed-size arrays referring to other items, where
+/// `len_of` returns the length specified by the item.
+pub(crate) fn check_array_lens(
+    value: &Value,
+    ty: &ConfigType,
+    len_of: &dyn Fn(&str) -> ConfigResult<usize>,
+) -> ConfigResult<()> {
+    match (value, ty) {
+        (_, ConfigType::Alias(_, ty) | ConfigType::Mapped(ty, ..)) => {
+            check_array_lens(value, ty, len_of)
+        }
+        (Value::Array(arr), ConfigType::FixedArray(ty, len)) => {
+            if let ArrayLen::Item(name) = len {
+                let expected = len_of(name)?;
+                if arr.len() != expected {
+                    return Err(ConfigErr::Other(format!(
+                        "Array `{}` has {} elements, but `{}` = {}",
+                        to_toml(value),
+                        arr.len(),
+                        name,
+                        expected
+                    )));
+                }
+            }
+            arr.iter().try_for_each(|v| check_array_lens(v, ty, len_of))
+        }
+        (Value::Array(arr), ConfigType::Array(ty)) => {
+            arr.iter().try_for_each(|v| check_array_lens(v, ty, len_of))
+        }
+        (Value::Array(arr), ConfigType::Tuple(types)) => arr
+            .iter()
+            .zip(types)
+            .try_for_each(|(v, ty)| check_array_lens(v, ty, len_of)),
+        (_, ConfigType::Option(ty)) if !is_none_value(value) => check_array_lens(value, ty, len_of),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the combined value of the flags, where the value is an array of
+/// flag names or an integer without unknown bits.
+pub(crate) fn flags_value(value: &Value, flags: &[(String, u64)]) -> Option<u64> {
+    match value {
+        Value::Array(names) => names.iter().try_fold(0, |acc, name| {
+            let name = name.as_str()?;
+            let (_, v) = flags.iter().find(|(n, _)| n == name)?;
+            Some(acc | v)
+        }),
+        _ => {
+            let v = u64::try_from(value_to_int(value)?).ok()?;
+            let all = flags.iter().fold(0, |acc, (_, v)| acc | v);
+            (v & !all == 0).then_some(v)
+        }
+    }
+}
+
+fn is_none_value(value: &Value) -> bool {
+    value.as_str() == Some(NONE)
+}
+
 fn value_type_matches(value: &Value, ty: &ConfigType) -> bool {
     match (value, ty) {
+        (_, ConfigType::Alias(_, ty) | ConfigType::Mapped(ty, ..)) => value_type_matches(value, ty),
+        (_, ConfigType::Option(ty)) => is_none_value(value) || value_type_matches(value, ty),
+        (_, ConfigType::Flags(flags)) => flags_value(value, flags).is_some(),
         (Value::Boolean(_), ConfigType::Bool) => true,
         (Value::Integer(_), ConfigType::Int | ConfigType::Uint) => true,
+        // integers are also assignable to floats
+        (Value::Integer(_), ConfigType::F32 | ConfigType::F64) => true,
+        (Value::Float(_), ConfigType::F64) => true,
+        (Value::Float(f), ConfigType::F32) => float_fits_f32(*f.value()),
+        (Value::String(s), ConfigType::Char) => s.value().chars().count() == 1,
         (Value::String(s), _) => {
             let s = s.value();
             if is_num(s) {
@@ -170,10 +619,66 @@ fn value_type_matches(value: &Value, ty: &ConfigType) -> bool {
             }
             true
         }
+        (Value::Array(arr), ConfigType::FixedArray(ty, len)) => {
+            // lengths referring to other items are checked by `check_array_lens`
+            if matches!(len, ArrayLen::Fixed(n) if *n != arr.len()) {
+                return false;
+            }
+            arr.iter().all(|e| value_type_matches(e, ty))
+        }
         _ => false,
     }
 }
 
+/// Returns whether the float is in the range of `f32`, where infinities and
+/// NaN are allowed.
+fn float_fits_f32(f: f64) -> bool {
+    !f.is_finite() || f.abs() <= f32::MAX as f64
+}
+
+fn value_to_float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(f) => Some(*f.value()),
+        _ => value_to_int(value).map(|v| v as f64),
+    }
+}
+
+fn value_eq(a: &Value, b: &Value, ty: Option<&ConfigType>) -> bool {
+    if let Some(ConfigType::Option(ty) | ConfigType::Alias(_, ty) | ConfigType::Mapped(ty, ..)) = ty
+    {
+        return value_eq(a, b, Some(ty));
+    }
+    if let Some(ConfigType::Flags(flags)) = ty {
+        return matches!(
+            (flags_value(a, flags), flags_value(b, flags)),
+            (Some(x), Some(y)) if x == y
+        );
+    }
+    match (a, b) {
+        (Value::Boolean(x), Value::Boolean(y)) => x.value() == y.value(),
+        (Value::String(x), Value::String(y)) if x.value() == y.value() => true,
+        (Value::Array(x), Value::Array(y)) => {
+            if x.len() != y.len() {
+                return false;
+            }
+            x.iter().zip(y.iter()).enumerate().all(|(i, (x, y))| {
+                let elem_ty = match ty {
+                    Some(ConfigType::Array(t) | ConfigType::FixedArray(t, _)) => Some(t.as_ref()),
+                    Some(ConfigType::Tuple(ts)) => ts.get(i),
+                    _ => None,
+                };
+                value_eq(x, y, elem_ty)
+            })
+        }
+        _ if matches!(ty, Some(ConfigType::String | ConfigType::Char)) => false,
+        _ if a.is_float() || b.is_float() => match (value_to_float(a), value_to_float(b)) {
+            (Some(x), Some(y)) => x == y || (x.is_nan() && y.is_nan()),
+            _ => false,
+        },
+        _ => matches!((value_to_int(a), value_to_int(b)), (Some(x), Some(y)) if x == y),
+    }
+}
+
 fn inferred_type(value: &Value) -> ConfigResult<ConfigType> {
     match value {
         Value::Boolean(_) => Ok(ConfigType::Bool),
@@ -185,6 +690,7 @@ fn inferred_type(value: &Value) -> ConfigResult<ConfigType> {
                 Ok(ConfigType::Uint)
             }
         }
+        Value::Float(_) => Ok(ConfigType::F64),
         Value::String(s) => {
             let s = s.value();
             if is_num(s) {
@@ -227,6 +733,7 @@ pub fn to_toml(value: &Value) -> String {
     match &value {
         Value::Boolean(b) => b.display_repr().to_string(),
         Value::Integer(i) => i.display_repr().to_string(),
+        Value::Float(f) => f.display_repr().to_string(),
         Value::String(s) => s.display_repr().to_string(),
         Value::Array(arr) => {
             let elements = arr.iter().map(to_toml).collect::<Vec<_>>();
@@ -240,15 +747,70 @@ pub fn to_toml(value: &Value) -> String {
     }
 }
 
+/// Converts the decoded string into a Rust string literal.
+///
+/// A raw string is used if the string contains backslashes or quotes but no
+/// control characters, otherwise special characters are escaped.
+fn rust_str(s: &str) -> String {
+    if s.contains(['\\', '"']) && !s.chars().any(char::is_control) {
+        // the number of `#` must exceed the longest run of `#` after a quote
+        let hashes = s
+            .split('"')
+            .skip(1)
+            .map(|part| part.len() - part.trim_start_matches('#').len())
+            .max()
+            .map_or(0, |n| n + 1);
+        let hashes = "#".repeat(hashes);
+        format!("r{}\"{}\"{}", hashes, s, hashes)
+    } else {
+        format!("{:?}", s)
+    }
+}
+
+/// Converts the float into a Rust literal of the float type `ty`, where
+/// infinities and NaN are converted into the associated constants.
+fn rust_float(f: f64, ty: &ConfigType) -> String {
+    if f.is_nan() {
+        format!("{}::NAN", ty)
+    } else if f.is_infinite() {
+        let sign = if f > 0.0 { "" } else { "NEG_" };
+        format!("{}::{}INFINITY", ty, sign)
+    } else {
+        // the `Debug` output always has a decimal point or an exponent
+        format!("{:?}", f)
+    }
+}
+
 pub fn to_rust(value: &Value, ty: &ConfigType, indent: usize) -> ConfigResult<String> {
     match (value, ty) {
+        (_, ConfigType::Alias(_, ty)) => to_rust(value, ty, indent),
+        (_, ConfigType::Mapped(ty, path, ctor)) => {
+            let value = to_rust(value, ty, indent)?;
+            match ctor {
+                Some(ctor) => Ok(format!("{}::{}({})", path, ctor, value)),
+                None => Ok(value),
+            }
+        }
+        (_, ConfigType::Option(_)) if is_none_value(value) => Ok("None".into()),
+        (_, ConfigType::Option(ty)) => Ok(format!("Some({})", to_rust(value, ty, indent)?)),
+        (_, ConfigType::Flags(flags)) => flags_value(value, flags)
+            .map(|v| format!("{:#x}", v))
+            .ok_or(ConfigErr::ValueTypeMismatch),
         (Value::Boolean(b), ConfigType::Bool) => Ok(b.display_repr().to_string()),
         (Value::Integer(i), ConfigType::Int | ConfigType::Uint) => Ok(i.display_repr().to_string()),
+        (Value::Integer(i), ConfigType::F32 | ConfigType::F64) => {
+            Ok(rust_float(*i.value() as f64, ty))
+        }
+        (Value::Float(f), ConfigType::F32 | ConfigType::F64) => Ok(rust_float(*f.value(), ty)),
+        (Value::String(s), ConfigType::Char) => match s.value().chars().collect::<Vec<_>>()[..] {
+            [c] => Ok(format!("{:?}", c)),
+            _ => Err(ConfigErr::ValueTypeMismatch),
+        },
         (Value::String(s), _) => {
             if matches!(ty, ConfigType::Int | ConfigType::Uint) {
                 Ok(s.value().to_string())
             } else if matches!(ty, ConfigType::String) {
-                Ok(s.display_repr().to_string())
+                Ok(rust_str(s.value()))
             } else {
                 Err(ConfigErr::ValueTypeMismatch)
             }
@@ -264,6 +826,11 @@ pub fn to_rust(value: &Value, ty: &ConfigType, indent: usize) -> ConfigResult<St
                 .collect::<ConfigResult<Vec<_>>>()?;
             Ok(format!("({})", elements.join(", ")))
         }
+        (Value::Array(_), ConfigType::FixedArray(ty, _)) => {
+            // the same as slices without the leading `&`
+            let slice = ConfigType::Array(ty.clone());
+            Ok(to_rust(value, &slice, indent)?[1..].into())
+        }
         (Value::Array(arr), ConfigType::Array(ty)) => {
             let elements = arr
                 .iter()
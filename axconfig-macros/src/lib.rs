@@ -2,12 +2,12 @@
 #![doc = include_str!("../README.md")]
 
 use proc_macro::{LexError, TokenStream};
-use quote::{quote, ToTokens};
+use quote::{quote, quote_spanned, ToTokens};
 use syn::parse::{Parse, ParseStream};
 use syn::parse_macro_input;
 use syn::{Error, Ident, LitStr, Result, Token};
 
-use axconfig_gen::{Config, OutputFormat};
+use axconfig_gen::{Config, ConfigResult, OutputFormat};
 
 fn compiler_error<T: ToTokens>(tokens: T, msg: String) -> TokenStream {
     Error::new_spanned(tokens, msg).to_compile_error().into()
@@ -31,7 +31,11 @@ pub fn parse_configs(config_toml: TokenStream) -> TokenStream {
     };
 
     let config_toml = parse_macro_input!(config_toml as LitStr).value();
-    let code = Config::from_toml(&config_toml).and_then(|cfg| cfg.dump(OutputFormat::Rust));
+    let code = Config::from_toml(&config_toml).and_then(|mut cfg| {
+        cfg.resolve_variants()?;
+        cfg.check_rules()?;
+        cfg.dump(OutputFormat::Rust)
+    });
     match code {
         Ok(code) => code
             .parse()
@@ -53,22 +57,44 @@ pub fn parse_configs(config_toml: TokenStream) -> TokenStream {
 /// include_configs!(path_env = "AX_CONFIG_PATH", fallback = "path/to/defconfig.toml");
 /// ```
 ///
+/// The values of config items can be overridden by environment variables
+/// with the `env_prefix` parameter. E.g., the following code overrides
+/// `kernel.task-stack-size` with `AX_CONFIG_KERNEL__TASK_STACK_SIZE` if it is
+/// set, and the crate is rebuilt when any of these variables changes.
+///
+/// ```rust,ignore
+/// include_configs!("path/to/config.toml", env_prefix = "AX_CONFIG_");
+/// ```
+///
+/// If the `spec` parameter is given, the config file is treated as an old
+/// config, and is used to update the config specification at that path, in the
+/// same way as `axconfig-gen -c`. The warnings of the update (e.g., unknown or
+/// mismatched items) are emitted as compile warnings.
+///
+/// ```rust,ignore
+/// include_configs!("path/to/.axconfig.toml", spec = "path/to/defconfig.toml");
+/// ```
+///
+/// The files may include other files with the `include` directive (see
+/// [`Config::from_file`]), and the crate is rebuilt when any of the files read
+/// changes.
+///
 /// See the [crate-level documentation][crate] for more details.
 #[proc_macro]
 pub fn include_configs(args: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args as IncludeConfigsArgs);
-    let path = match args {
-        IncludeConfigsArgs::Path(p) => p.value(),
-        IncludeConfigsArgs::PathEnv(env) => {
+    let path = match &args.path {
+        ConfigPath::Path(p) => p.value(),
+        ConfigPath::PathEnv(env) => {
             let Ok(path) = std::env::var(env.value()) else {
                 return compiler_error(
-                    &env,
+                    env,
                     format!("environment variable `{}` not set", env.value()),
                 );
             };
             path
         }
-        IncludeConfigsArgs::PathEnvFallback(env, fallback) => {
+        ConfigPath::PathEnvFallback(env, fallback) => {
             std::env::var(env.value()).unwrap_or_else(|_| fallback.value())
         }
     };
@@ -76,17 +102,77 @@ pub fn include_configs(args: TokenStream) -> TokenStream {
     let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into());
     let cfg_path = std::path::Path::new(&root).join(&path);
 
-    let Ok(config_toml) = std::fs::read_to_string(&cfg_path) else {
-        return compiler_error(path, format!("failed to read config file: {:?}", cfg_path));
+    let mut files = Vec::new();
+    let mut env_vars = Vec::new();
+    let mut warnings = Vec::new();
+    let code = (|| -> ConfigResult<String> {
+        let mut read_config = |path: &std::path::Path| {
+            Config::from_file_with(path, |path| files.push(path.display().to_string()))
+        };
+        let mut cfg = match &args.spec {
+            Some(spec) => {
+                let spec_path = std::path::Path::new(&root).join(spec.value());
+                let mut cfg = read_config(&spec_path)?;
+                let report = cfg.update(&read_config(&cfg_path)?)?;
+                warnings.extend(report.warnings().iter().map(|w| w.to_string()));
+                cfg
+            }
+            None => read_config(&cfg_path)?,
+        };
+        if let Some(env_prefix) = &args.env_prefix {
+            cfg.apply_env(&env_prefix.value(), |name| {
+                env_vars.push(name.to_string());
+                std::env::var(name).ok()
+            })?;
+        }
+        cfg.resolve_variants()?;
+        cfg.check_rules()?;
+        cfg.dump(OutputFormat::Rust)
+    })();
+    let path_lit = match &args.path {
+        ConfigPath::Path(p) | ConfigPath::PathEnv(p) | ConfigPath::PathEnvFallback(p, _) => p,
     };
-
+    let span = args
+        .spec
+        .as_ref()
+        .or(args.env_prefix.as_ref())
+        .unwrap_or(path_lit);
+    let code: proc_macro2::TokenStream = match code {
+        Ok(code) => match code.parse() {
+            Ok(code) => code,
+            Err(e) => return compiler_error(span, e.to_string()),
+        },
+        Err(e) => return compiler_error(span, e.to_string()),
+    };
+    // There is no stable way to emit warnings from a proc macro, so use of a
+    // deprecated constant is generated for each warning of the update.
+    let warnings = warnings.iter().map(|msg| {
+        quote_spanned! {span.span()=>
+            const _: () = {
+                #[deprecated(note = #msg)]
+                const AXCONFIG_WARNING: () = ();
+                AXCONFIG_WARNING
+            };
+        }
+    });
+    // `option_env!` and `include_bytes!` make cargo rebuild the crate when the
+    // variables or the files change.
     quote! {
-        ::axconfig_macros::parse_configs!(#config_toml);
+        #code
+        #(#warnings)*
+        const _: () = { #(let _ = option_env!(#env_vars);)* };
+        #(const _: &[u8] = include_bytes!(#files);)*
     }
     .into()
 }
 
-enum IncludeConfigsArgs {
+struct IncludeConfigsArgs {
+    path: ConfigPath,
+    env_prefix: Option<LitStr>,
+    spec: Option<LitStr>,
+}
+
+enum ConfigPath {
     Path(LitStr),
     PathEnv(LitStr),
     PathEnvFallback(LitStr, LitStr),
@@ -94,50 +180,63 @@ enum IncludeConfigsArgs {
 
 impl Parse for IncludeConfigsArgs {
     fn parse(input: ParseStream) -> Result<Self> {
+        let mut path = None;
         if input.peek(LitStr) {
-            return Ok(IncludeConfigsArgs::Path(input.parse()?));
+            path = Some(input.parse()?);
+            if input.peek(Token![,]) {
+                input.parse::<Token![,]>()?;
+            }
         }
 
         let mut env = None;
         let mut fallback = None;
+        let mut env_prefix = None;
+        let mut spec = None;
         while !input.is_empty() {
             let ident: Ident = input.parse()?;
             input.parse::<Token![=]>()?;
             let str: LitStr = input.parse()?;
 
-            match ident.to_string().as_str() {
-                "path_env" => {
-                    if env.is_some() {
-                        return Err(Error::new(ident.span(), "duplicate parameter `path_env`"));
-                    }
-                    env = Some(str);
-                }
-                "fallback" => {
-                    if fallback.is_some() {
-                        return Err(Error::new(ident.span(), "duplicate parameter `fallback`"));
-                    }
-                    fallback = Some(str);
-                }
+            let param = match ident.to_string().as_str() {
+                "path_env" if path.is_none() => &mut env,
+                "fallback" if path.is_none() => &mut fallback,
+                "env_prefix" => &mut env_prefix,
+                "spec" => &mut spec,
                 _ => {
                     return Err(Error::new(
                         ident.span(),
                         format!("unexpected parameter `{}`", ident),
                     ))
                 }
+            };
+            if param.is_some() {
+                return Err(Error::new(
+                    ident.span(),
+                    format!("duplicate parameter `{}`", ident),
+                ));
             }
+            *param = Some(str);
 
             if input.peek(Token![,]) {
                 input.parse::<Token![,]>()?;
             }
         }
 
-        match (env, fallback) {
-            (Some(env), None) => Ok(IncludeConfigsArgs::PathEnv(env)),
-            (Some(env), Some(fallback)) => Ok(IncludeConfigsArgs::PathEnvFallback(env, fallback)),
-            _ => Err(Error::new(
-                input.span(),
-                "missing required parameter `path_env`",
-            )),
-        }
+        let path = match (path, env, fallback) {
+            (Some(path), _, _) => ConfigPath::Path(path),
+            (None, Some(env), None) => ConfigPath::PathEnv(env),
+            (None, Some(env), Some(fallback)) => ConfigPath::PathEnvFallback(env, fallback),
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "missing required parameter `path_env`",
+                ))
+            }
+        };
+        Ok(IncludeConfigsArgs {
+            path,
+            env_prefix,
+            spec,
+        })
     }
 }
@@ -5,6 +5,113 @@ mod config {
     include_configs!("../example-configs/defconfig.toml"); // root: CARGO_MANIFEST_DIR
 }
 
+mod config_env {
+    include_configs!(
+        "../example-configs/defconfig.toml",
+        env_prefix = "AXCONFIG_MACROS_TEST_"
+    );
+}
+
+// the variables are set in `.cargo/config.toml`
+mod config_env_set {
+    include_configs!(
+        "../example-configs/defconfig.toml",
+        env_prefix = "AXCONFIG_MACROS_TEST_SET_"
+    );
+}
+
+mod config_spec {
+    include_configs!(
+        "../example-configs/output.toml",
+        spec = "../example-configs/defconfig.toml"
+    );
+}
+
+mod config_strings {
+    parse_configs!(
+        r##"
+        basic = "say \"hi\"\t\U0001F600"
+        literal = 'C:\path\"#'
+        multi-line = """
+line 1 \
+    line 2"""
+        multi-line-literal = '''
+C:\dir\n'''
+        "##
+    );
+}
+
+mod config_idents {
+    parse_configs!(
+        r#"
+        2nd-uart = 2
+        "foo.bar" = 3
+        self = 4
+        [type]
+        crate = 5
+        "#
+    );
+}
+
+mod config_types {
+    parse_configs!(
+        r#"
+        pci-ecam-base = "none"      # uint?
+        rtc-base = "0x1000"         # option<uint>
+        smp = 2                     # uint
+        cpu-ids = [0, 1]            # [uint; smp]
+        cpu-ghz = 2                 # f64
+        timer-ratio = 1.5e-3        # f32
+        sep = "/"                   # char
+        mmio-attrs = ["READ", "EXEC"] # flags(READ=0x1, WRITE=0x2, EXEC=0x4)
+        regions = [[0, 0x1000]]     # [region]
+        [percpu]
+        stacks = [[1, 2], [3, 4]]   # [[uint; 2]; smp]
+        [__types__]
+        region = "(uint, uint)"
+        "#
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysAddr(usize);
+
+impl PhysAddr {
+    pub const fn from_usize(addr: usize) -> Self {
+        Self(addr)
+    }
+}
+
+mod config_mapped {
+    parse_configs!(
+        r#"
+        phys-memory-base = 0x4000_0000  # uint as crate::PhysAddr via from_usize
+        irq = 5                         # uint as u32
+        [devices]
+        mmio-regions = [[0xfec0_0000, 0x1000]] # [(uint as crate::PhysAddr via from_usize, uint)]
+        "#
+    );
+}
+
+mod config_variants {
+    parse_configs!(
+        r#"
+        arch = "aarch64"
+        [kernel]
+        ticks-per-sec = { default = 100, aarch64 = 1000 }   # uint, by("arch")
+        task-stack-size = { default = 0x40000, x86_64 = 0x80000 } # uint, by("arch")
+        "#
+    );
+}
+
+// the selector is changed by the old config
+mod config_variants_spec {
+    include_configs!(
+        "tests/configs/variants.toml",
+        spec = "tests/configs/variants.spec.toml"
+    );
+}
+
 #[cfg(feature = "nightly")]
 mod config2 {
     parse_configs!(include_str!("../../example-configs/defconfig.toml"));
@@ -79,8 +186,85 @@ fn test_include_configs() {
     mod_cmp!(config, config_expect);
 }
 
+#[test]
+fn test_include_configs_env() {
+    mod_cmp!(config_env, config_expect);
+}
+
+#[test]
+fn test_include_configs_env_set() {
+    assert_eq!(config_env_set::SMP, 4);
+    assert_eq!(config_env_set::ARCH, "1234");
+    assert_eq!(config_env_set::kernel::TASK_STACK_SIZE, 0x8_0000);
+    assert_eq!(config_env_set::PLAT, config_expect::PLAT);
+}
+
+#[test]
+fn test_include_configs_spec() {
+    mod_cmp!(config_spec, config_expect);
+}
+
+#[test]
+fn test_string_literals() {
+    assert_eq!(config_strings::BASIC, "say \"hi\"\t\u{1F600}");
+    assert_eq!(config_strings::LITERAL, "C:\\path\\\"#");
+    assert_eq!(config_strings::MULTI_LINE, "line 1 line 2");
+    assert_eq!(config_strings::MULTI_LINE_LITERAL, "C:\\dir\\n");
+}
+
+#[test]
+fn test_rust_idents() {
+    assert_eq!(config_idents::_2ND_UART, 2);
+    assert_eq!(config_idents::FOO_BAR, 3);
+    assert_eq!(config_idents::SELF, 4);
+    assert_eq!(config_idents::r#type::CRATE, 5);
+}
+
+#[test]
+fn test_types() {
+    assert_eq!(config_types::PCI_ECAM_BASE, None);
+    assert_eq!(config_types::RTC_BASE, Some(0x1000));
+    assert_eq!(config_types::CPU_IDS, [0, 1]);
+    assert_eq!(config_types::percpu::STACKS.len(), config_types::SMP);
+    assert_eq!(config_types::percpu::STACKS[1], [3, 4]);
+    assert_eq!(config_types::CPU_GHZ, 2.0);
+    assert_eq!(config_types::TIMER_RATIO, 1.5e-3);
+    assert_eq!(config_types::SEP, '/');
+    assert_eq!(config_types::MMIO_ATTRS, 0x5);
+    let region: config_types::Region = config_types::REGIONS[0];
+    assert_eq!(region, (0, 0x1000));
+}
+
+#[test]
+fn test_mapped_types() {
+    assert_eq!(
+        config_mapped::PHYS_MEMORY_BASE,
+        PhysAddr::from_usize(0x4000_0000)
+    );
+    let irq: u32 = config_mapped::IRQ;
+    assert_eq!(irq, 5);
+    assert_eq!(
+        config_mapped::devices::MMIO_REGIONS,
+        [(PhysAddr::from_usize(0xfec0_0000), 0x1000)]
+    );
+}
+
+#[test]
+fn test_variants() {
+    assert_eq!(config_variants::kernel::TICKS_PER_SEC, 1000);
+    assert_eq!(config_variants::kernel::TASK_STACK_SIZE, 0x40000);
+    assert_eq!(config_variants_spec::ARCH, "riscv64");
+    assert_eq!(config_variants_spec::kernel::TICKS_PER_SEC, 500);
+}
+
 #[cfg(feature = "nightly")]
 #[test]
 fn test_parse_configs() {
     mod_cmp!(config2, config_expect);
 }
+
+#[test]
+fn test_spec_warnings() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/spec_warnings.rs");
+}
@@ -0,0 +1,12 @@
+#![deny(deprecated)]
+
+#[macro_use]
+extern crate axconfig_macros;
+
+// the paths are relative to the test project in `target/tests/trybuild`
+include_configs!(
+    "../../../../axconfig-macros/tests/ui/spec_warnings.toml",
+    spec = "../../../../axconfig-macros/tests/ui/spec_warnings.spec.toml"
+);
+
+fn main() {}
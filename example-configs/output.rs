@@ -1,3 +1,6 @@
+/// Memory region with format (`base_paddr`, `size`).
+pub type Region = (usize, usize);
+
 /// Architecture identifier.
 pub const ARCH: &str = "x86_64";
 /// Platform identifier.
@@ -10,7 +13,7 @@ pub const SMP: usize = 1;
 ///
 pub mod devices {
     /// MMIO regions with format (`base_paddr`, `size`).
-    pub const MMIO_REGIONS: &[(usize, usize)] = &[
+    pub const MMIO_REGIONS: &[super::Region] = &[
         (0xb000_0000, 0x1000_0000),
         (0xfe00_0000, 0xc0_0000),
         (0xfec0_0000, 0x1000),
@@ -22,9 +25,9 @@ pub mod devices {
     /// Base physical address of the PCIe ECAM space (should read from ACPI 'MCFG' table).
     pub const PCI_ECAM_BASE: usize = 0;
     /// PCI device memory ranges (not used on x86).
-    pub const PCI_RANGES: &[(usize, usize)] = &[];
+    pub const PCI_RANGES: &[super::Region] = &[];
     /// VirtIO MMIO regions with format (`base_paddr`, `size`).
-    pub const VIRTIO_MMIO_REGIONS: &[(usize, usize)] = &[];
+    pub const VIRTIO_MMIO_REGIONS: &[super::Region] = &[];
 }
 
 ///